@@ -0,0 +1,159 @@
+#![no_main]
+
+//! Decode-then-check fuzz target for the x86-64 backend, in the style of
+//! SVSM's instruction fuzz target: synthesize a random `Assembler` program,
+//! push it through the real `compile_with_regs` pipeline, then disassemble
+//! the bytes that came out and make sure every one of them decodes as part
+//! of a valid x86-64 instruction. Which mnemonic each decoded instruction
+//! has is not checked against the op that produced it. A panic here -- whether
+//! from the `unreachable_patterns` arm in `x86_emit`, an assertion below, or
+//! Capstone refusing to decode what we wrote -- is the bug this target
+//! exists to find. Corpus entries that trigger one are kept under
+//! `corpus/x86_emit_roundtrip/` by `cargo fuzz` so they can be replayed and
+//! minimized.
+
+use arbitrary::{Arbitrary, Unstructured};
+use capstone::prelude::*;
+use libfuzzer_sys::fuzz_target;
+
+use yjit::asm::CodeBlock;
+use yjit::backend::ir::{Assembler, Opnd};
+use yjit::backend::x86_64::{RAX_REG, RCX_REG, RDX_REG};
+
+/// The handful of two-operand ALU/compare/move ops the existing
+/// `test_emit_*` golden-hex tests already cover by hand -- randomized and
+/// run end to end instead of one fixed case at a time.
+#[derive(Debug, Arbitrary)]
+enum FuzzOp {
+    Add,
+    Sub,
+    And,
+    Or,
+    Xor,
+    Cmp,
+    Test,
+    Mov,
+}
+
+/// Bias immediates toward the 32-bit sign/zero-extension boundary --
+/// `0xFF`, `0xFFFF_FFFF`, `0xFFFF_FFFF_FFFF` -- that `test_emit_add_gt_32_bits`
+/// and friends show is where encoding bugs actually hide, instead of
+/// spreading uniformly over the full 64-bit range.
+fn fuzz_imm(u: &mut Unstructured) -> arbitrary::Result<u64> {
+    const EDGE_VALUES: &[u64] = &[
+        0x0,
+        0xFF,
+        0x7FFF_FFFF,
+        0x8000_0000,
+        0xFFFF_FFFF,
+        0xFFFF_FFFF_FFFF,
+        u64::MAX,
+    ];
+    Ok(*u.choose(EDGE_VALUES)?)
+}
+
+fn fuzz_opnd(u: &mut Unstructured) -> arbitrary::Result<Opnd> {
+    Ok(match u.int_in_range(0..=2)? {
+        0 => Opnd::Reg(RAX_REG),
+        1 => Opnd::Reg(RCX_REG),
+        _ => Opnd::mem(64, Opnd::Reg(RDX_REG), 8 * i32::from(u.arbitrary::<i8>()?)),
+    })
+}
+
+/// Disassemble every byte the emitter wrote and assert it forms a stream of
+/// valid x86-64 instructions -- a silently-wrong encoding (as opposed to an
+/// outright panic) would otherwise only show up much later as a crash deep
+/// inside generated Ruby code.
+fn assert_decodes(label: &str, cb: &CodeBlock) {
+    let hex = format!("{:x}", cb);
+    let bytes: Vec<u8> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("cb hex dump is always well-formed"))
+        .collect();
+
+    if bytes.is_empty() {
+        return;
+    }
+
+    let cs = Capstone::new()
+        .x86()
+        .mode(arch::x86::ArchMode::Mode64)
+        .build()
+        .expect("capstone should always initialize for a fixed, supported arch/mode");
+
+    let insns = cs.disasm_all(&bytes, 0x1000).unwrap_or_else(|e| {
+        panic!("{label}: emitted bytes {hex} failed to decode as x86-64: {e}")
+    });
+
+    assert!(!insns.is_empty(), "{label}: emitted {} bytes but decoded zero instructions", bytes.len());
+
+    // `disasm_all` stops quietly at the first byte it can't decode, so make
+    // sure the decoded instructions account for every emitted byte.
+    let decoded_len: usize = insns.iter().map(|insn| insn.bytes().len()).sum();
+    assert_eq!(decoded_len, bytes.len(), "{label}: only {decoded_len} of {} emitted bytes ({hex}) decoded as x86-64", bytes.len());
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+
+    let Ok(op_count) = u.int_in_range(1..=24) else { return };
+
+    // Build the same program twice so it can be emitted into both a
+    // generously-sized buffer and a cramped one below, the way a single
+    // `Assembler` can only be compiled once (`compile_with_regs` consumes
+    // `self`).
+    let build = |u: &mut Unstructured| -> arbitrary::Result<Assembler> {
+        let mut asm = Assembler::new();
+        let label = asm.new_label("fuzz_target");
+
+        for _ in 0..op_count {
+            let op = FuzzOp::arbitrary(u)?;
+            let left = fuzz_opnd(u)?;
+            let right = if bool::arbitrary(u)? {
+                Opnd::UImm(fuzz_imm(u)?)
+            } else {
+                fuzz_opnd(u)?
+            };
+
+            match op {
+                FuzzOp::Add => { let _ = asm.add(left, right); },
+                FuzzOp::Sub => { let _ = asm.sub(left, right); },
+                FuzzOp::And => { let _ = asm.and(left, right); },
+                FuzzOp::Or => { let _ = asm.or(left, right); },
+                FuzzOp::Xor => { let _ = asm.xor(left, right); },
+                FuzzOp::Cmp => asm.cmp(left, right),
+                FuzzOp::Test => asm.test(left, right),
+                FuzzOp::Mov => asm.mov(left, right),
+            }
+
+            // Occasionally throw in a jump to the trailing label so
+            // `Target::Label` operands get exercised too.
+            if bool::arbitrary(u)? {
+                asm.jmp(label);
+            }
+        }
+
+        asm.write_label(label);
+        Ok(asm)
+    };
+
+    let Ok(roomy_asm) = build(&mut u) else { return };
+    let regs = Assembler::get_alloc_regs();
+
+    let mut roomy_cb = CodeBlock::new_dummy(4096);
+    roomy_asm.compile_with_regs(&mut roomy_cb, regs.clone());
+    assert_decodes("single-page", &roomy_cb);
+
+    // Re-run the same random program through a CodeBlock tight enough that
+    // at least one instruction has to retry via `cb.next_page`, and check
+    // the page-spanning path decodes cleanly too. `new_dummy` sizes the
+    // single backing page to the requested byte count, so a buffer much
+    // smaller than the roomy one above forces that retry path without
+    // needing direct control over the page size.
+    let mut replay = Unstructured::new(data);
+    let Ok(_) = replay.int_in_range(1..=24) else { return };
+    let Ok(cramped_asm) = build(&mut replay) else { return };
+    let mut cramped_cb = CodeBlock::new_dummy(64);
+    cramped_asm.compile_with_regs(&mut cramped_cb, regs);
+    assert_decodes("page-spanning retry", &cramped_cb);
+});
@@ -1,5 +1,18 @@
+use std::fmt;
 use crate::asm::{imm_num_bits, uimm_num_bits};
 
+/// Encoding slot 31 is overloaded on AArch64: depending on the instruction,
+/// it names either the stack pointer or the zero register. We tag which
+/// meaning is intended so the emit layer can pick the right encoding (and
+/// reject a register used where only the other role is legal) instead of
+/// silently doing the wrong thing.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Reg31Role
+{
+    SP,
+    Zero,
+}
+
 /// This operand represents a register.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct A64Reg
@@ -9,34 +22,232 @@ pub struct A64Reg
 
     // Register index number
     pub reg_no: u8,
+
+    // Which of SP/XZR register 31 names here. `None` for registers 0..30,
+    // where there is no ambiguity.
+    pub reg31_role: Option<Reg31Role>,
 }
 
-#[derive(Clone, Copy, Debug)]
-pub struct A64Mem
+impl A64Reg {
+    /// Creates a plain general-purpose register (reg_no 0..30).
+    pub const fn new(num_bits: u8, reg_no: u8) -> Self {
+        Self { num_bits, reg_no, reg31_role: None }
+    }
+
+    /// Creates the stack-pointer interpretation of register 31.
+    pub const fn new_sp(num_bits: u8) -> Self {
+        Self { num_bits, reg_no: 31, reg31_role: Some(Reg31Role::SP) }
+    }
+
+    /// Creates the zero-register interpretation of register 31.
+    pub const fn new_zero(num_bits: u8) -> Self {
+        Self { num_bits, reg_no: 31, reg31_role: Some(Reg31Role::Zero) }
+    }
+
+    /// True if this is the stack-pointer interpretation of register 31.
+    pub fn is_sp(&self) -> bool {
+        self.reg31_role == Some(Reg31Role::SP)
+    }
+
+    /// True if this is the zero-register interpretation of register 31.
+    pub fn is_zero_reg(&self) -> bool {
+        self.reg31_role == Some(Reg31Role::Zero)
+    }
+
+    /// Asserts that this register can be used where `role` is required. Used
+    /// by instruction builders/the emit layer to reject e.g. passing `SP`
+    /// where only `XZR` is legal (or vice versa) rather than silently
+    /// emitting the wrong encoding.
+    pub fn expect_role(&self, role: Reg31Role) {
+        if self.reg_no == 31 {
+            assert_eq!(self.reg31_role, Some(role), "register 31 used with the wrong SP/XZR role");
+        }
+    }
+}
+
+/// The arrangement of a vector register operand, i.e. how its 128 (or 64)
+/// bits are split into lanes. This mirrors the `.<N><T>` suffix that shows up
+/// in NEON assembly (e.g. `v0.4s`) as well as the scalar FP views (`d0`, `s0`).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VecArrangement
 {
-    // Size in bits
+    // Scalar floating-point views (no lanes)
+    B,
+    H,
+    S,
+    D,
+
+    // Vector views, named after the NEON `.<N><T>` suffix
+    B8X16,
+    H4X8,
+    S2X4,
+    D1X2,
+}
+
+/// This operand represents a SIMD/FP register, i.e. one of V0..V31. Unlike
+/// the general-purpose file, these registers can be addressed at several
+/// widths (Q/D/S/H/B) and, for vector instructions, as a set of lanes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct A64VReg
+{
+    // Size in bits of the view being used (8, 16, 32, 64, or 128)
     pub num_bits: u8,
 
-    /// Base register number
-    pub base_reg_no: u8,
+    // Register index number (0..31)
+    pub reg_no: u8,
+
+    // How the register's bits are arranged into lanes for this operand
+    pub arrangement: VecArrangement,
+}
+
+/// The shift applied to a `RegShift` operand, e.g. the `, lsl #3` part of
+/// `add x0, x1, x2, lsl #3`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ShiftOp
+{
+    LSL,
+    LSR,
+    ASR,
+    ROR,
+}
+
+/// The extension applied to a register operand, either as the index of a
+/// `BaseIndex` memory operand (e.g. `[x1, x2, uxtw]`) or as a `RegExtend`
+/// data-processing operand (e.g. `add x0, x1, w2, uxtw #2`). Only a subset of
+/// these (`UXTW`/`SXTW`/`LSL`) is legal in the memory-index position.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExtendOp
+{
+    UXTB,
+    UXTH,
+    UXTW,
+    UXTX,
+    SXTB,
+    SXTH,
+    SXTW,
+    SXTX,
+    LSL,
+}
+
+/// AArch64 memory operand. Unlike x86, AArch64 exposes several distinct
+/// addressing modes instead of one general `base + disp` form, so this is
+/// modeled as an enum rather than a single struct.
+#[derive(Clone, Copy, Debug)]
+pub enum A64Mem
+{
+    /// `[base, #disp]` — base register plus a constant displacement. This is
+    /// today's (and the common) addressing mode.
+    BaseDisp { num_bits: u8, base_reg_no: u8, disp: i32 },
+
+    /// `[base, index{, extend #shift_amount}]` — base register plus an index
+    /// register, optionally zero/sign-extended and left-shifted to realize a
+    /// `disp = index * scale` pattern.
+    BaseIndex { num_bits: u8, base_reg_no: u8, index_reg_no: u8, extend: Option<ExtendOp>, shift_amount: u8 },
 
-    /// Constant displacement from the base, not scaled
-    pub disp: i32,
+    /// `[base, #disp]!` — the effective address is `base + disp`, which is
+    /// also written back into `base`.
+    PreIndex { num_bits: u8, base_reg_no: u8, disp: i32 },
+
+    /// `[base], #disp` — the effective address is `base`, and `base + disp`
+    /// is written back into `base` afterwards.
+    PostIndex { num_bits: u8, base_reg_no: u8, disp: i32 },
 }
 
 impl A64Mem {
+    /// Creates a `BaseDisp` memory operand, validating that `disp` fits the
+    /// scaled 12-bit unsigned immediate field used by the base+disp load/store
+    /// forms (or the signed 9-bit unscaled field when it doesn't fit).
     pub fn new(reg: A64Opnd, disp: i32) -> Self {
         match reg {
             A64Opnd::Reg(reg) => {
-                Self {
-                    num_bits: reg.num_bits,
-                    base_reg_no: reg.reg_no,
-                    disp
+                let num_bits = reg.num_bits;
+                assert!(
+                    Self::disp_fits_bits(num_bits, disp),
+                    "disp {disp} does not fit the 12-bit scaled or 9-bit unscaled immediate field for a {num_bits}-bit access"
+                );
+
+                Self::BaseDisp { num_bits, base_reg_no: reg.reg_no, disp }
+            },
+            _ => panic!("Expected register operand")
+        }
+    }
+
+    /// Creates a `BaseIndex` memory operand.
+    pub fn new_base_index(base: A64Opnd, index: A64Opnd, extend: Option<ExtendOp>, shift_amount: u8) -> Self {
+        match (base, index) {
+            (A64Opnd::Reg(base), A64Opnd::Reg(index)) => {
+                assert!(shift_amount <= 4, "shift amount {shift_amount} out of range 0..4");
+                assert!(
+                    matches!(extend, None | Some(ExtendOp::UXTW | ExtendOp::SXTW | ExtendOp::LSL)),
+                    "only UXTW, SXTW, or LSL are legal extensions for a memory index register"
+                );
+
+                Self::BaseIndex {
+                    num_bits: base.num_bits,
+                    base_reg_no: base.reg_no,
+                    index_reg_no: index.reg_no,
+                    extend,
+                    shift_amount
                 }
             },
+            _ => panic!("Expected register operands")
+        }
+    }
+
+    /// Creates a `PreIndex` memory operand, validating that `disp` fits the
+    /// signed 9-bit immediate field used by the pre/post-indexed forms.
+    pub fn new_pre_index(reg: A64Opnd, disp: i32) -> Self {
+        match reg {
+            A64Opnd::Reg(reg) => {
+                assert!(imm_num_bits(disp.into()) <= 9, "disp {disp} does not fit the signed 9-bit immediate field");
+                Self::PreIndex { num_bits: reg.num_bits, base_reg_no: reg.reg_no, disp }
+            },
+            _ => panic!("Expected register operand")
+        }
+    }
+
+    /// Creates a `PostIndex` memory operand, validating that `disp` fits the
+    /// signed 9-bit immediate field used by the pre/post-indexed forms.
+    pub fn new_post_index(reg: A64Opnd, disp: i32) -> Self {
+        match reg {
+            A64Opnd::Reg(reg) => {
+                assert!(imm_num_bits(disp.into()) <= 9, "disp {disp} does not fit the signed 9-bit immediate field");
+                Self::PostIndex { num_bits: reg.num_bits, base_reg_no: reg.reg_no, disp }
+            },
             _ => panic!("Expected register operand")
         }
     }
+
+    /// Size in bits of the value being addressed.
+    pub fn num_bits(&self) -> u8 {
+        match *self {
+            Self::BaseDisp { num_bits, .. } |
+            Self::BaseIndex { num_bits, .. } |
+            Self::PreIndex { num_bits, .. } |
+            Self::PostIndex { num_bits, .. } => num_bits
+        }
+    }
+
+    /// Base register number, common to every addressing mode.
+    pub fn base_reg_no(&self) -> u8 {
+        match *self {
+            Self::BaseDisp { base_reg_no, .. } |
+            Self::BaseIndex { base_reg_no, .. } |
+            Self::PreIndex { base_reg_no, .. } |
+            Self::PostIndex { base_reg_no, .. } => base_reg_no
+        }
+    }
+
+    /// Checks whether `disp` fits either the scaled 12-bit unsigned immediate
+    /// field (the common case for naturally-aligned displacements) or the
+    /// signed 9-bit unscaled immediate field that AArch64 falls back to.
+    fn disp_fits_bits(num_bits: u8, disp: i32) -> bool {
+        let access_size = (num_bits / 8) as i32;
+        let scaled_fits = disp >= 0 && disp % access_size == 0 && uimm_num_bits((disp / access_size) as u64) <= 12;
+        let unscaled_fits = imm_num_bits(disp.into()) <= 9;
+
+        scaled_fits || unscaled_fits
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -54,6 +265,16 @@ pub enum A64Opnd
     // Register
     Reg(A64Reg),
 
+    // SIMD/FP register
+    VReg(A64VReg),
+
+    // A register shifted by an immediate amount, e.g. `x2, lsl #3`
+    RegShift { reg: A64Reg, shift_op: ShiftOp, amount: u8 },
+
+    // A register extended (and optionally shifted) by an immediate amount,
+    // e.g. `w2, sxtw #2`
+    RegExtend { reg: A64Reg, extend_op: ExtendOp, amount: u8 },
+
     // Memory
     Mem(A64Mem)
 }
@@ -69,11 +290,26 @@ impl A64Opnd {
         A64Opnd::UImm(value)
     }
 
-    /// Creates a new memory operand.
+    /// Creates a new `base + disp` memory operand.
     pub fn new_mem(reg: A64Opnd, disp: i32) -> Self {
         A64Opnd::Mem(A64Mem::new(reg, disp))
     }
 
+    /// Creates a new `base + index{, extend #shift_amount}` memory operand.
+    pub fn new_mem_base_index(base: A64Opnd, index: A64Opnd, extend: Option<ExtendOp>, shift_amount: u8) -> Self {
+        A64Opnd::Mem(A64Mem::new_base_index(base, index, extend, shift_amount))
+    }
+
+    /// Creates a new pre-indexed `[base, #disp]!` memory operand.
+    pub fn new_mem_pre_index(reg: A64Opnd, disp: i32) -> Self {
+        A64Opnd::Mem(A64Mem::new_pre_index(reg, disp))
+    }
+
+    /// Creates a new post-indexed `[base], #disp` memory operand.
+    pub fn new_mem_post_index(reg: A64Opnd, disp: i32) -> Self {
+        A64Opnd::Mem(A64Mem::new_post_index(reg, disp))
+    }
+
     /// Convenience function to check if this operand is a register.
     pub fn is_reg(&self) -> bool {
         match self {
@@ -81,81 +317,535 @@ impl A64Opnd {
             _ => false
         }
     }
+
+    /// Convenience function to check if this operand is a vector register.
+    pub fn is_vreg(&self) -> bool {
+        match self {
+            A64Opnd::VReg(_) => true,
+            _ => false
+        }
+    }
+
+    /// Unwraps the underlying `A64Reg`, panicking if this isn't a plain
+    /// register operand. Used by the shift/extend builder helpers below,
+    /// which only make sense applied to a GPR.
+    fn unwrap_reg(&self) -> A64Reg {
+        match self {
+            A64Opnd::Reg(reg) => *reg,
+            _ => panic!("Expected register operand")
+        }
+    }
+
+    /// Returns this register shifted left by `amount` (`lsl #amount`).
+    pub fn lsl(&self, amount: u8) -> Self {
+        self.shift(ShiftOp::LSL, amount)
+    }
+
+    /// Returns this register shifted right, unsigned (`lsr #amount`).
+    pub fn lsr(&self, amount: u8) -> Self {
+        self.shift(ShiftOp::LSR, amount)
+    }
+
+    /// Returns this register shifted right, sign-extending (`asr #amount`).
+    pub fn asr(&self, amount: u8) -> Self {
+        self.shift(ShiftOp::ASR, amount)
+    }
+
+    /// Returns this register rotated right (`ror #amount`). Illegal on the
+    /// add/sub shifted-register form; only some instructions accept it.
+    pub fn ror(&self, amount: u8) -> Self {
+        self.shift(ShiftOp::ROR, amount)
+    }
+
+    fn shift(&self, shift_op: ShiftOp, amount: u8) -> Self {
+        let reg = self.unwrap_reg();
+        let max_amount = if reg.num_bits == 32 { 31 } else { 63 };
+        assert!(amount <= max_amount, "shift amount {amount} out of range for a {}-bit register", reg.num_bits);
+
+        A64Opnd::RegShift { reg, shift_op, amount }
+    }
+
+    /// Returns this register zero-extended from a byte, then shifted left by
+    /// `amount` (`uxtb #amount`).
+    pub fn uxtb(&self, amount: u8) -> Self { self.extend(ExtendOp::UXTB, amount) }
+
+    /// Returns this register zero-extended from a halfword, then shifted
+    /// left by `amount` (`uxth #amount`).
+    pub fn uxth(&self, amount: u8) -> Self { self.extend(ExtendOp::UXTH, amount) }
+
+    /// Returns this register zero-extended from a word, then shifted left by
+    /// `amount` (`uxtw #amount`).
+    pub fn uxtw(&self, amount: u8) -> Self { self.extend(ExtendOp::UXTW, amount) }
+
+    /// Returns this register zero-extended (a no-op on a 64-bit source),
+    /// then shifted left by `amount` (`uxtx #amount`).
+    pub fn uxtx(&self, amount: u8) -> Self { self.extend(ExtendOp::UXTX, amount) }
+
+    /// Returns this register sign-extended from a byte, then shifted left by
+    /// `amount` (`sxtb #amount`).
+    pub fn sxtb(&self, amount: u8) -> Self { self.extend(ExtendOp::SXTB, amount) }
+
+    /// Returns this register sign-extended from a halfword, then shifted
+    /// left by `amount` (`sxth #amount`).
+    pub fn sxth(&self, amount: u8) -> Self { self.extend(ExtendOp::SXTH, amount) }
+
+    /// Returns this register sign-extended from a word, then shifted left by
+    /// `amount` (`sxtw #amount`).
+    pub fn sxtw(&self, amount: u8) -> Self { self.extend(ExtendOp::SXTW, amount) }
+
+    /// Returns this register sign-extended (a no-op on a 64-bit source),
+    /// then shifted left by `amount` (`sxtx #amount`).
+    pub fn sxtx(&self, amount: u8) -> Self { self.extend(ExtendOp::SXTX, amount) }
+
+    fn extend(&self, extend_op: ExtendOp, amount: u8) -> Self {
+        let reg = self.unwrap_reg();
+        assert!(amount <= 4, "extend amount {amount} out of range 0..4");
+
+        A64Opnd::RegExtend { reg, extend_op, amount }
+    }
 }
 
-pub const X0_REG: A64Reg = A64Reg { num_bits: 64, reg_no: 0 };
-pub const X1_REG: A64Reg = A64Reg { num_bits: 64, reg_no: 1 };
-pub const X2_REG: A64Reg = A64Reg { num_bits: 64, reg_no: 2 };
-pub const X3_REG: A64Reg = A64Reg { num_bits: 64, reg_no: 3 };
+pub const X0_REG: A64Reg = A64Reg::new(64, 0);
+pub const X1_REG: A64Reg = A64Reg::new(64, 1);
+pub const X2_REG: A64Reg = A64Reg::new(64, 2);
+pub const X3_REG: A64Reg = A64Reg::new(64, 3);
 
-pub const X12_REG: A64Reg = A64Reg { num_bits: 64, reg_no: 12 };
-pub const X13_REG: A64Reg = A64Reg { num_bits: 64, reg_no: 13 };
+pub const X12_REG: A64Reg = A64Reg::new(64, 12);
+pub const X13_REG: A64Reg = A64Reg::new(64, 13);
 
 // 64-bit registers
 pub const X0: A64Opnd = A64Opnd::Reg(X0_REG);
 pub const X1: A64Opnd = A64Opnd::Reg(X1_REG);
 pub const X2: A64Opnd = A64Opnd::Reg(X2_REG);
 pub const X3: A64Opnd = A64Opnd::Reg(X3_REG);
-pub const X4: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 4 });
-pub const X5: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 5 });
-pub const X6: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 6 });
-pub const X7: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 7 });
-pub const X8: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 8 });
-pub const X9: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 9 });
-pub const X10: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 10 });
-pub const X11: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 11 });
+pub const X4: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 4));
+pub const X5: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 5));
+pub const X6: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 6));
+pub const X7: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 7));
+pub const X8: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 8));
+pub const X9: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 9));
+pub const X10: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 10));
+pub const X11: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 11));
 pub const X12: A64Opnd = A64Opnd::Reg(X12_REG);
 pub const X13: A64Opnd = A64Opnd::Reg(X13_REG);
-pub const X14: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 14 });
-pub const X15: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 15 });
-pub const X16: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 16 });
-pub const X17: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 17 });
-pub const X18: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 18 });
-pub const X19: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 19 });
-pub const X20: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 20 });
-pub const X21: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 21 });
-pub const X22: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 22 });
-pub const X23: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 23 });
-pub const X24: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 24 });
-pub const X25: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 25 });
-pub const X26: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 26 });
-pub const X27: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 27 });
-pub const X28: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 28 });
-pub const X29: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 29 });
-pub const X30: A64Opnd = A64Opnd::Reg(A64Reg { num_bits: 64, reg_no: 30 });
+pub const X14: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 14));
+pub const X15: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 15));
+pub const X16: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 16));
+pub const X17: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 17));
+pub const X18: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 18));
+pub const X19: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 19));
+pub const X20: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 20));
+pub const X21: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 21));
+pub const X22: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 22));
+pub const X23: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 23));
+pub const X24: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 24));
+pub const X25: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 25));
+pub const X26: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 26));
+pub const X27: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 27));
+pub const X28: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 28));
+pub const X29: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 29));
+pub const X30: A64Opnd = A64Opnd::Reg(A64Reg::new(64, 30));
 
 // 32-bit registers
-pub const W0: A64Reg = A64Reg { num_bits: 32, reg_no: 0 };
-pub const W1: A64Reg = A64Reg { num_bits: 32, reg_no: 1 };
-pub const W2: A64Reg = A64Reg { num_bits: 32, reg_no: 2 };
-pub const W3: A64Reg = A64Reg { num_bits: 32, reg_no: 3 };
-pub const W4: A64Reg = A64Reg { num_bits: 32, reg_no: 4 };
-pub const W5: A64Reg = A64Reg { num_bits: 32, reg_no: 5 };
-pub const W6: A64Reg = A64Reg { num_bits: 32, reg_no: 6 };
-pub const W7: A64Reg = A64Reg { num_bits: 32, reg_no: 7 };
-pub const W8: A64Reg = A64Reg { num_bits: 32, reg_no: 8 };
-pub const W9: A64Reg = A64Reg { num_bits: 32, reg_no: 9 };
-pub const W10: A64Reg = A64Reg { num_bits: 32, reg_no: 10 };
-pub const W11: A64Reg = A64Reg { num_bits: 32, reg_no: 11 };
-pub const W12: A64Reg = A64Reg { num_bits: 32, reg_no: 12 };
-pub const W13: A64Reg = A64Reg { num_bits: 32, reg_no: 13 };
-pub const W14: A64Reg = A64Reg { num_bits: 32, reg_no: 14 };
-pub const W15: A64Reg = A64Reg { num_bits: 32, reg_no: 15 };
-pub const W16: A64Reg = A64Reg { num_bits: 32, reg_no: 16 };
-pub const W17: A64Reg = A64Reg { num_bits: 32, reg_no: 17 };
-pub const W18: A64Reg = A64Reg { num_bits: 32, reg_no: 18 };
-pub const W19: A64Reg = A64Reg { num_bits: 32, reg_no: 19 };
-pub const W20: A64Reg = A64Reg { num_bits: 32, reg_no: 20 };
-pub const W21: A64Reg = A64Reg { num_bits: 32, reg_no: 21 };
-pub const W22: A64Reg = A64Reg { num_bits: 32, reg_no: 22 };
-pub const W23: A64Reg = A64Reg { num_bits: 32, reg_no: 23 };
-pub const W24: A64Reg = A64Reg { num_bits: 32, reg_no: 24 };
-pub const W25: A64Reg = A64Reg { num_bits: 32, reg_no: 25 };
-pub const W26: A64Reg = A64Reg { num_bits: 32, reg_no: 26 };
-pub const W27: A64Reg = A64Reg { num_bits: 32, reg_no: 27 };
-pub const W28: A64Reg = A64Reg { num_bits: 32, reg_no: 28 };
-pub const W29: A64Reg = A64Reg { num_bits: 32, reg_no: 29 };
-pub const W30: A64Reg = A64Reg { num_bits: 32, reg_no: 30 };
+pub const W0: A64Reg = A64Reg::new(32, 0);
+pub const W1: A64Reg = A64Reg::new(32, 1);
+pub const W2: A64Reg = A64Reg::new(32, 2);
+pub const W3: A64Reg = A64Reg::new(32, 3);
+pub const W4: A64Reg = A64Reg::new(32, 4);
+pub const W5: A64Reg = A64Reg::new(32, 5);
+pub const W6: A64Reg = A64Reg::new(32, 6);
+pub const W7: A64Reg = A64Reg::new(32, 7);
+pub const W8: A64Reg = A64Reg::new(32, 8);
+pub const W9: A64Reg = A64Reg::new(32, 9);
+pub const W10: A64Reg = A64Reg::new(32, 10);
+pub const W11: A64Reg = A64Reg::new(32, 11);
+pub const W12: A64Reg = A64Reg::new(32, 12);
+pub const W13: A64Reg = A64Reg::new(32, 13);
+pub const W14: A64Reg = A64Reg::new(32, 14);
+pub const W15: A64Reg = A64Reg::new(32, 15);
+pub const W16: A64Reg = A64Reg::new(32, 16);
+pub const W17: A64Reg = A64Reg::new(32, 17);
+pub const W18: A64Reg = A64Reg::new(32, 18);
+pub const W19: A64Reg = A64Reg::new(32, 19);
+pub const W20: A64Reg = A64Reg::new(32, 20);
+pub const W21: A64Reg = A64Reg::new(32, 21);
+pub const W22: A64Reg = A64Reg::new(32, 22);
+pub const W23: A64Reg = A64Reg::new(32, 23);
+pub const W24: A64Reg = A64Reg::new(32, 24);
+pub const W25: A64Reg = A64Reg::new(32, 25);
+pub const W26: A64Reg = A64Reg::new(32, 26);
+pub const W27: A64Reg = A64Reg::new(32, 27);
+pub const W28: A64Reg = A64Reg::new(32, 28);
+pub const W29: A64Reg = A64Reg::new(32, 29);
+pub const W30: A64Reg = A64Reg::new(32, 30);
+
+// Register 31, which is either the stack pointer or the zero register
+// depending on which instruction (and which operand position) it's used in.
+pub const SP: A64Opnd = A64Opnd::Reg(A64Reg::new_sp(64));
+pub const WSP: A64Opnd = A64Opnd::Reg(A64Reg::new_sp(32));
+pub const XZR: A64Opnd = A64Opnd::Reg(A64Reg::new_zero(64));
+pub const WZR: A64Opnd = A64Opnd::Reg(A64Reg::new_zero(32));
 
 // C argument registers
 pub const C_ARG_REGS: [A64Opnd; 4] = [X0, X1, X2, X3];
+
+// SIMD/FP registers (V0..V31), viewed at the 128-bit (Q) width by default.
+// The narrower scalar and lane-arrangement views of each Vn are defined
+// below as separate constants.
+macro_rules! def_vreg {
+    ($name:ident, $reg_no:expr, $num_bits:expr, $arrangement:expr) => {
+        pub const $name: A64Opnd = A64Opnd::VReg(A64VReg { num_bits: $num_bits, reg_no: $reg_no, arrangement: $arrangement });
+    };
+}
+
+def_vreg!(V0, 0, 128, VecArrangement::B8X16);
+def_vreg!(V1, 1, 128, VecArrangement::B8X16);
+def_vreg!(V2, 2, 128, VecArrangement::B8X16);
+def_vreg!(V3, 3, 128, VecArrangement::B8X16);
+def_vreg!(V4, 4, 128, VecArrangement::B8X16);
+def_vreg!(V5, 5, 128, VecArrangement::B8X16);
+def_vreg!(V6, 6, 128, VecArrangement::B8X16);
+def_vreg!(V7, 7, 128, VecArrangement::B8X16);
+def_vreg!(V8, 8, 128, VecArrangement::B8X16);
+def_vreg!(V9, 9, 128, VecArrangement::B8X16);
+def_vreg!(V10, 10, 128, VecArrangement::B8X16);
+def_vreg!(V11, 11, 128, VecArrangement::B8X16);
+def_vreg!(V12, 12, 128, VecArrangement::B8X16);
+def_vreg!(V13, 13, 128, VecArrangement::B8X16);
+def_vreg!(V14, 14, 128, VecArrangement::B8X16);
+def_vreg!(V15, 15, 128, VecArrangement::B8X16);
+def_vreg!(V16, 16, 128, VecArrangement::B8X16);
+def_vreg!(V17, 17, 128, VecArrangement::B8X16);
+def_vreg!(V18, 18, 128, VecArrangement::B8X16);
+def_vreg!(V19, 19, 128, VecArrangement::B8X16);
+def_vreg!(V20, 20, 128, VecArrangement::B8X16);
+def_vreg!(V21, 21, 128, VecArrangement::B8X16);
+def_vreg!(V22, 22, 128, VecArrangement::B8X16);
+def_vreg!(V23, 23, 128, VecArrangement::B8X16);
+def_vreg!(V24, 24, 128, VecArrangement::B8X16);
+def_vreg!(V25, 25, 128, VecArrangement::B8X16);
+def_vreg!(V26, 26, 128, VecArrangement::B8X16);
+def_vreg!(V27, 27, 128, VecArrangement::B8X16);
+def_vreg!(V28, 28, 128, VecArrangement::B8X16);
+def_vreg!(V29, 29, 128, VecArrangement::B8X16);
+def_vreg!(V30, 30, 128, VecArrangement::B8X16);
+def_vreg!(V31, 31, 128, VecArrangement::B8X16);
+
+// Scalar 64-bit (D) view of each vector register
+def_vreg!(D0, 0, 64, VecArrangement::D);
+def_vreg!(D1, 1, 64, VecArrangement::D);
+def_vreg!(D2, 2, 64, VecArrangement::D);
+def_vreg!(D3, 3, 64, VecArrangement::D);
+def_vreg!(D4, 4, 64, VecArrangement::D);
+def_vreg!(D5, 5, 64, VecArrangement::D);
+def_vreg!(D6, 6, 64, VecArrangement::D);
+def_vreg!(D7, 7, 64, VecArrangement::D);
+def_vreg!(D8, 8, 64, VecArrangement::D);
+def_vreg!(D9, 9, 64, VecArrangement::D);
+def_vreg!(D10, 10, 64, VecArrangement::D);
+def_vreg!(D11, 11, 64, VecArrangement::D);
+def_vreg!(D12, 12, 64, VecArrangement::D);
+def_vreg!(D13, 13, 64, VecArrangement::D);
+def_vreg!(D14, 14, 64, VecArrangement::D);
+def_vreg!(D15, 15, 64, VecArrangement::D);
+def_vreg!(D16, 16, 64, VecArrangement::D);
+def_vreg!(D17, 17, 64, VecArrangement::D);
+def_vreg!(D18, 18, 64, VecArrangement::D);
+def_vreg!(D19, 19, 64, VecArrangement::D);
+def_vreg!(D20, 20, 64, VecArrangement::D);
+def_vreg!(D21, 21, 64, VecArrangement::D);
+def_vreg!(D22, 22, 64, VecArrangement::D);
+def_vreg!(D23, 23, 64, VecArrangement::D);
+def_vreg!(D24, 24, 64, VecArrangement::D);
+def_vreg!(D25, 25, 64, VecArrangement::D);
+def_vreg!(D26, 26, 64, VecArrangement::D);
+def_vreg!(D27, 27, 64, VecArrangement::D);
+def_vreg!(D28, 28, 64, VecArrangement::D);
+def_vreg!(D29, 29, 64, VecArrangement::D);
+def_vreg!(D30, 30, 64, VecArrangement::D);
+def_vreg!(D31, 31, 64, VecArrangement::D);
+
+// Scalar 32-bit (S) view of each vector register
+def_vreg!(S0, 0, 32, VecArrangement::S);
+def_vreg!(S1, 1, 32, VecArrangement::S);
+def_vreg!(S2, 2, 32, VecArrangement::S);
+def_vreg!(S3, 3, 32, VecArrangement::S);
+def_vreg!(S4, 4, 32, VecArrangement::S);
+def_vreg!(S5, 5, 32, VecArrangement::S);
+def_vreg!(S6, 6, 32, VecArrangement::S);
+def_vreg!(S7, 7, 32, VecArrangement::S);
+def_vreg!(S8, 8, 32, VecArrangement::S);
+def_vreg!(S9, 9, 32, VecArrangement::S);
+def_vreg!(S10, 10, 32, VecArrangement::S);
+def_vreg!(S11, 11, 32, VecArrangement::S);
+def_vreg!(S12, 12, 32, VecArrangement::S);
+def_vreg!(S13, 13, 32, VecArrangement::S);
+def_vreg!(S14, 14, 32, VecArrangement::S);
+def_vreg!(S15, 15, 32, VecArrangement::S);
+def_vreg!(S16, 16, 32, VecArrangement::S);
+def_vreg!(S17, 17, 32, VecArrangement::S);
+def_vreg!(S18, 18, 32, VecArrangement::S);
+def_vreg!(S19, 19, 32, VecArrangement::S);
+def_vreg!(S20, 20, 32, VecArrangement::S);
+def_vreg!(S21, 21, 32, VecArrangement::S);
+def_vreg!(S22, 22, 32, VecArrangement::S);
+def_vreg!(S23, 23, 32, VecArrangement::S);
+def_vreg!(S24, 24, 32, VecArrangement::S);
+def_vreg!(S25, 25, 32, VecArrangement::S);
+def_vreg!(S26, 26, 32, VecArrangement::S);
+def_vreg!(S27, 27, 32, VecArrangement::S);
+def_vreg!(S28, 28, 32, VecArrangement::S);
+def_vreg!(S29, 29, 32, VecArrangement::S);
+def_vreg!(S30, 30, 32, VecArrangement::S);
+def_vreg!(S31, 31, 32, VecArrangement::S);
+
+// 128-bit (Q) full-register view, named explicitly for callers that want to
+// make the width obvious at the call site instead of relying on V<n>.
+def_vreg!(Q0, 0, 128, VecArrangement::B8X16);
+def_vreg!(Q1, 1, 128, VecArrangement::B8X16);
+def_vreg!(Q2, 2, 128, VecArrangement::B8X16);
+def_vreg!(Q3, 3, 128, VecArrangement::B8X16);
+def_vreg!(Q4, 4, 128, VecArrangement::B8X16);
+def_vreg!(Q5, 5, 128, VecArrangement::B8X16);
+def_vreg!(Q6, 6, 128, VecArrangement::B8X16);
+def_vreg!(Q7, 7, 128, VecArrangement::B8X16);
+def_vreg!(Q8, 8, 128, VecArrangement::B8X16);
+def_vreg!(Q9, 9, 128, VecArrangement::B8X16);
+def_vreg!(Q10, 10, 128, VecArrangement::B8X16);
+def_vreg!(Q11, 11, 128, VecArrangement::B8X16);
+def_vreg!(Q12, 12, 128, VecArrangement::B8X16);
+def_vreg!(Q13, 13, 128, VecArrangement::B8X16);
+def_vreg!(Q14, 14, 128, VecArrangement::B8X16);
+def_vreg!(Q15, 15, 128, VecArrangement::B8X16);
+def_vreg!(Q16, 16, 128, VecArrangement::B8X16);
+def_vreg!(Q17, 17, 128, VecArrangement::B8X16);
+def_vreg!(Q18, 18, 128, VecArrangement::B8X16);
+def_vreg!(Q19, 19, 128, VecArrangement::B8X16);
+def_vreg!(Q20, 20, 128, VecArrangement::B8X16);
+def_vreg!(Q21, 21, 128, VecArrangement::B8X16);
+def_vreg!(Q22, 22, 128, VecArrangement::B8X16);
+def_vreg!(Q23, 23, 128, VecArrangement::B8X16);
+def_vreg!(Q24, 24, 128, VecArrangement::B8X16);
+def_vreg!(Q25, 25, 128, VecArrangement::B8X16);
+def_vreg!(Q26, 26, 128, VecArrangement::B8X16);
+def_vreg!(Q27, 27, 128, VecArrangement::B8X16);
+def_vreg!(Q28, 28, 128, VecArrangement::B8X16);
+def_vreg!(Q29, 29, 128, VecArrangement::B8X16);
+def_vreg!(Q30, 30, 128, VecArrangement::B8X16);
+def_vreg!(Q31, 31, 128, VecArrangement::B8X16);
+
+//
+// Pretty-printing
+//
+// These produce canonical ARM assembly text for debug dumps, e.g. so emitted
+// operands can be diffed against a reference disassembler while validating
+// new instruction encodings.
+//
+
+/// Renders a GPR name, using the ABI aliases for fp/lr/sp and the `w` prefix
+/// for 32-bit accesses. Register 31 is rendered as the stack pointer, which
+/// is its meaning when used as a memory operand's base register; `A64Reg`'s
+/// own `Display` impl overrides this for the XZR case.
+fn gpr_name(num_bits: u8, reg_no: u8) -> String {
+    match reg_no {
+        29 if num_bits == 64 => "fp".to_string(),
+        30 if num_bits == 64 => "lr".to_string(),
+        31 if num_bits == 32 => "wsp".to_string(),
+        31 => "sp".to_string(),
+        _ => format!("{}{reg_no}", if num_bits == 32 { "w" } else { "x" })
+    }
+}
+
+impl fmt::Display for A64Reg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.reg_no == 31 {
+            let name = match self.reg31_role {
+                Some(Reg31Role::SP) => if self.num_bits == 32 { "wsp" } else { "sp" },
+                Some(Reg31Role::Zero) => if self.num_bits == 32 { "wzr" } else { "xzr" },
+                None => unreachable!("register 31 without a role"),
+            };
+            write!(f, "{name}")
+        } else {
+            write!(f, "{}", gpr_name(self.num_bits, self.reg_no))
+        }
+    }
+}
+
+impl fmt::Display for VecArrangement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let suffix = match self {
+            Self::B => "b",
+            Self::H => "h",
+            Self::S => "s",
+            Self::D => "d",
+            Self::B8X16 => "16b",
+            Self::H4X8 => "8h",
+            Self::S2X4 => "4s",
+            Self::D1X2 => "2d",
+        };
+        write!(f, "{suffix}")
+    }
+}
+
+impl fmt::Display for A64VReg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.arrangement {
+            VecArrangement::B | VecArrangement::H | VecArrangement::S | VecArrangement::D => {
+                write!(f, "{}{}", self.arrangement, self.reg_no)
+            },
+            _ => write!(f, "v{}.{}", self.reg_no, self.arrangement)
+        }
+    }
+}
+
+impl fmt::Display for ShiftOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::LSL => "lsl",
+            Self::LSR => "lsr",
+            Self::ASR => "asr",
+            Self::ROR => "ror",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for ExtendOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::UXTB => "uxtb",
+            Self::UXTH => "uxth",
+            Self::UXTW => "uxtw",
+            Self::UXTX => "uxtx",
+            Self::SXTB => "sxtb",
+            Self::SXTH => "sxth",
+            Self::SXTW => "sxtw",
+            Self::SXTX => "sxtx",
+            Self::LSL => "lsl",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for A64Mem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::BaseDisp { base_reg_no, disp, .. } => {
+                if disp == 0 {
+                    write!(f, "[{}]", gpr_name(64, base_reg_no))
+                } else {
+                    write!(f, "[{}, #{:#x}]", gpr_name(64, base_reg_no), disp)
+                }
+            },
+            Self::BaseIndex { base_reg_no, index_reg_no, extend, shift_amount, .. } => {
+                write!(f, "[{}, {}", gpr_name(64, base_reg_no), gpr_name(64, index_reg_no))?;
+                if let Some(extend) = extend {
+                    write!(f, ", {extend} #{shift_amount}")?;
+                } else if shift_amount != 0 {
+                    write!(f, ", lsl #{shift_amount}")?;
+                }
+                write!(f, "]")
+            },
+            Self::PreIndex { base_reg_no, disp, .. } => {
+                write!(f, "[{}, #{:#x}]!", gpr_name(64, base_reg_no), disp)
+            },
+            Self::PostIndex { base_reg_no, disp, .. } => {
+                write!(f, "[{}], #{:#x}", gpr_name(64, base_reg_no), disp)
+            },
+        }
+    }
+}
+
+impl fmt::Display for A64Opnd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Imm(value) => write!(f, "#{value:#x}"),
+            Self::UImm(value) => write!(f, "#{value:#x}"),
+            Self::Reg(reg) => write!(f, "{reg}"),
+            Self::VReg(vreg) => write!(f, "{vreg}"),
+            Self::RegShift { reg, shift_op, amount } => write!(f, "{reg}, {shift_op} #{amount}"),
+            Self::RegExtend { reg, extend_op, amount } => write!(f, "{reg}, {extend_op} #{amount}"),
+            Self::Mem(mem) => write!(f, "{mem}"),
+        }
+    }
+}
+
+//
+// Register-class newtypes
+//
+// Wrapping `A64Reg`/`A64VReg` in a per-class newtype turns "wrong operand
+// kind" mistakes (passing a vector register where a base address is
+// expected, or vice versa) into a compile error instead of the runtime
+// `panic!("Expected register operand")` that `A64Mem::new` falls back to.
+// Instruction builders that only make sense over one register class should
+// take the matching wrapper instead of a raw `A64Opnd`.
+//
+
+/// A general-purpose register, guaranteed at construction time to come from
+/// the integer file (`X0..X30`/`SP`/`XZR` and their 32-bit `W` views).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Gpr(A64Reg);
+
+impl Gpr {
+    pub fn to_reg(self) -> A64Reg {
+        self.0
+    }
+}
+
+impl From<A64Reg> for Gpr {
+    fn from(reg: A64Reg) -> Self {
+        Gpr(reg)
+    }
+}
+
+impl TryFrom<A64Opnd> for Gpr {
+    type Error = ();
+
+    fn try_from(opnd: A64Opnd) -> Result<Self, Self::Error> {
+        match opnd {
+            A64Opnd::Reg(reg) => Ok(Gpr(reg)),
+            _ => Err(())
+        }
+    }
+}
+
+impl From<Gpr> for A64Opnd {
+    fn from(gpr: Gpr) -> Self {
+        A64Opnd::Reg(gpr.0)
+    }
+}
+
+/// A SIMD/FP register, guaranteed at construction time to come from the
+/// vector file (`V0..V31` and its `Q`/`D`/`S`/`H`/`B` views).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Fpr(A64VReg);
+
+impl Fpr {
+    pub fn to_reg(self) -> A64VReg {
+        self.0
+    }
+}
+
+impl From<A64VReg> for Fpr {
+    fn from(vreg: A64VReg) -> Self {
+        Fpr(vreg)
+    }
+}
+
+impl TryFrom<A64Opnd> for Fpr {
+    type Error = ();
+
+    fn try_from(opnd: A64Opnd) -> Result<Self, Self::Error> {
+        match opnd {
+            A64Opnd::VReg(vreg) => Ok(Fpr(vreg)),
+            _ => Err(())
+        }
+    }
+}
+
+impl From<Fpr> for A64Opnd {
+    fn from(fpr: Fpr) -> Self {
+        A64Opnd::VReg(fpr.0)
+    }
+}
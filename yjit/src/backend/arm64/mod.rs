@@ -0,0 +1,655 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+#![allow(unused_imports)]
+
+use std::mem::take;
+
+use crate::asm::*;
+use crate::asm::arm64::*;
+use crate::cruby::*;
+use crate::backend::ir::*;
+
+// Use the arm64 register type for this platform
+pub type Reg = A64Reg;
+
+// Callee-saved registers (x19-x28 in the AAPCS64 calling convention)
+pub const _CFP: Opnd = Opnd::Reg(A64Reg::new(64, 19));
+pub const _EC: Opnd = Opnd::Reg(A64Reg::new(64, 20));
+pub const _SP: Opnd = Opnd::Reg(A64Reg::new(64, 21));
+
+// C argument registers on this platform
+pub const _C_ARG_OPNDS: [Opnd; 6] = [
+    Opnd::Reg(A64Reg::new(64, 0)),
+    Opnd::Reg(A64Reg::new(64, 1)),
+    Opnd::Reg(A64Reg::new(64, 2)),
+    Opnd::Reg(A64Reg::new(64, 3)),
+    Opnd::Reg(A64Reg::new(64, 4)),
+    Opnd::Reg(A64Reg::new(64, 5)),
+];
+
+// C return value register on this platform
+pub const C_RET_REG: Reg = A64Reg::new(64, 0);
+pub const _C_RET_OPND: Opnd = Opnd::Reg(C_RET_REG);
+
+// The native (machine) stack pointer, used for register-allocator spill
+// slots. Distinct from `_SP`, which is the interpreter's Ruby-level stack
+// pointer aliased to a callee-saved register.
+pub const NATIVE_STACK_PTR_REG: Reg = A64Reg::new_sp(64);
+
+// `mul`/`sdiv`/`udiv`/`msub` take fully explicit operands on this platform,
+// so `SMul`/`UMul`/`SDiv`/`UDiv`/`SMod`/`UMod` have no implicit-register
+// constraint the way they do on x86_64. `alloc_regs` still pins their output
+// to a concrete register shared across both backends; arm64 just routes it
+// through `mov` like any other value instead of relying on it.
+pub const MULDIV_OUT_REG: Reg = A64Reg::new(64, 0);
+pub const MULDIV_REM_REG: Reg = A64Reg::new(64, 1);
+
+// The number of bytes generated by an unconditional branch to a raw pointer
+// (movz/movk x16, ...; br x16).
+pub const JMP_PTR_BYTES: usize = 20;
+
+/// Returns true if `value` fits the 12-bit unsigned immediate field used by
+/// `add`/`sub`/`cmp` on ARM64, either directly or shifted left by 12
+/// (`add reg, reg, #imm, lsl #12`).
+fn fits_imm12(value: u64) -> bool {
+    value <= 0xfff || (value & !(0xfff << 12)) == 0
+}
+
+/// Map Opnd to A64Opnd
+impl From<Opnd> for A64Opnd {
+    fn from(opnd: Opnd) -> Self {
+        match opnd {
+            // NOTE: these operand types need to be lowered first
+            //Value(VALUE),       // Immediate Ruby value, may be GC'd, movable
+            //InsnOut(usize),     // Output of a preceding instruction in this block
+
+            Opnd::InsnOut{..} => panic!("InsnOut operand made it past register allocation"),
+
+            Opnd::UImm(val) => A64Opnd::new_uimm(val),
+            Opnd::Imm(val) => A64Opnd::new_imm(val),
+            Opnd::Value(VALUE(uimm)) => A64Opnd::new_uimm(uimm as u64),
+
+            // General-purpose register
+            Opnd::Reg(reg) => A64Opnd::Reg(reg),
+
+            // Memory operand with displacement
+            Opnd::Mem(Mem { base: MemBase::Reg(reg_no), num_bits, disp }) => {
+                A64Opnd::new_mem(A64Opnd::Reg(A64Reg::new(num_bits, reg_no)), disp)
+            }
+
+            Opnd::None => panic!(
+                "Attempted to lower an Opnd::None. This often happens when an out operand was not allocated for an instruction because the output of the instruction was not used. Please ensure you are using the output."
+            ),
+
+            _ => panic!("unsupported arm64 operand type")
+        }
+    }
+}
+
+/// Also implement going from a reference to an operand for convenience.
+impl From<&Opnd> for A64Opnd {
+    fn from(opnd: &Opnd) -> Self {
+        A64Opnd::from(*opnd)
+    }
+}
+
+impl Assembler
+{
+    /// Get the list of registers from which we can allocate on this
+    /// platform. x9-x15 are caller-saved temporaries that aren't used to
+    /// pass arguments, so they're free for the register allocator to hand
+    /// out.
+    pub fn get_alloc_regs() -> Vec<Reg>
+    {
+        vec![
+            A64Reg::new(64, 9),
+            A64Reg::new(64, 10),
+            A64Reg::new(64, 11),
+        ]
+    }
+
+    /// Get a list of all of the caller-save registers
+    pub fn get_caller_save_regs() -> Vec<Reg> {
+        (0..18).map(|reg_no| A64Reg::new(64, reg_no)).collect()
+    }
+
+    /// Get the list of registers from which we can allocate for
+    /// floating-point values on this platform. D0-D2 are caller-saved and
+    /// otherwise unused by the rest of this backend, mirroring the x86_64
+    /// side's `XMM0_REG`-`XMM2_REG`.
+    pub fn get_fp_alloc_regs() -> Vec<Reg>
+    {
+        vec![
+            D0_REG,
+            D1_REG,
+            D2_REG,
+        ]
+    }
+
+    // These are the callee-saved registers in the AAPCS64 calling convention:
+    // x19-x28, plus the frame pointer x29 and link register x30.
+
+    /// Split IR instructions for the ARM64 platform.
+    ///
+    /// Unlike x86-64, ARM64 has no memory-operand arithmetic: every
+    /// data-processing instruction (`add`, `sub`, `and`, `cmp`, `tst`, ...)
+    /// requires its operands to already be in registers, so a generic
+    /// `Add(mem, imm)` has to be split into an explicit load, the arithmetic
+    /// op, and (when the destination is itself memory) a store performed by
+    /// the caller via a separate `Mov`. Immediates that don't fit the 12-bit
+    /// (optionally `lsl #12`-shifted) unsigned field also have to be
+    /// materialized into a scratch register first.
+    fn arm64_split(mut self) -> Assembler
+    {
+        let live_ranges: Vec<usize> = take(&mut self.live_ranges);
+        let mut asm = Assembler::new_with_label_names(take(&mut self.label_names));
+        let mut iterator = self.into_draining_iter();
+
+        while let Some((index, mut insn)) = iterator.next_unmapped() {
+            // See the equivalent comment in x86_split: we use next_unmapped
+            // here so we can inspect the pre-mapping operand kinds, and we
+            // must map every operand exactly once ourselves.
+            let mut unmapped_opnds: Vec<Opnd> = vec![];
+
+            let mut opnd_iter = insn.opnd_iter_mut();
+            while let Some(opnd) = opnd_iter.next() {
+                unmapped_opnds.push(*opnd);
+                *opnd = iterator.map_opnd(*opnd);
+            }
+
+            #[allow(unused_must_use)]
+            match insn.op {
+                Op::Add | Op::Sub | Op::And => {
+                    let mut left = insn.opnds[0];
+                    let mut right = insn.opnds[1];
+
+                    // ALU ops can't read memory directly, so load both sides.
+                    if let Opnd::Mem(_) = left {
+                        left = asm.load(left);
+                    }
+                    if let Opnd::Mem(_) = right {
+                        right = asm.load(right);
+                    }
+
+                    // Immediates that don't fit the 12-bit shifted encoding
+                    // have to be materialized into a register first.
+                    match right {
+                        Opnd::UImm(value) if !fits_imm12(value) => {
+                            right = asm.load(right);
+                        },
+                        Opnd::Imm(value) if !fits_imm12(value as u64) => {
+                            right = asm.load(right);
+                        },
+                        _ => {}
+                    }
+
+                    insn.opnds[0] = left;
+                    insn.opnds[1] = right;
+                    insn.out = asm.next_opnd_out(Opnd::match_num_bits(&[left, right]));
+                    asm.push_insn(insn);
+                },
+                Op::Mul | Op::SMul | Op::UMul | Op::SDiv | Op::UDiv | Op::SMod | Op::UMod => {
+                    // `mul`/`sdiv`/`udiv`/`msub` take fully explicit register
+                    // operands with no immediate form at all (unlike
+                    // `add`/`sub`'s 12-bit shifted immediate), so anything
+                    // that isn't already a register has to be loaded first.
+                    // Unlike x86_64, the instructions have no implicit
+                    // RAX:RDX-style operands; `alloc_regs` still places the
+                    // result of everything but `Mul` in `MULDIV_OUT_REG`
+                    // (`SMod`/`UMod`: `MULDIV_REM_REG`).
+                    let mut left = insn.opnds[0];
+                    let mut right = insn.opnds[1];
+
+                    if !matches!(left, Opnd::Reg(_) | Opnd::InsnOut { .. }) {
+                        left = asm.load(left);
+                    }
+                    if !matches!(right, Opnd::Reg(_) | Opnd::InsnOut { .. }) {
+                        right = asm.load(right);
+                    }
+
+                    insn.opnds[0] = left;
+                    insn.opnds[1] = right;
+                    insn.out = asm.next_opnd_out(Opnd::match_num_bits(&[left, right]));
+                    asm.push_insn(insn);
+                },
+                Op::FAdd | Op::FSub | Op::FMul | Op::FDiv => {
+                    // Same story as the integer ALU ops above, but there's no
+                    // immediate form at all for NEON/FP data-processing
+                    // instructions, so anything that isn't already a float
+                    // register has to go through `fload` first.
+                    let mut left = insn.opnds[0];
+                    let mut right = insn.opnds[1];
+
+                    if !matches!(left, Opnd::Reg(_) | Opnd::InsnOut { .. }) {
+                        left = asm.fload(left);
+                    }
+                    if !matches!(right, Opnd::Reg(_) | Opnd::InsnOut { .. }) {
+                        right = asm.fload(right);
+                    }
+
+                    insn.opnds[0] = left;
+                    insn.opnds[1] = right;
+                    insn.out = asm.next_opnd_out(Opnd::match_num_bits(&[left, right]));
+                    asm.push_insn(insn);
+                },
+                Op::Cmp | Op::Test | Op::CmpJcc => {
+                    let mut left = insn.opnds[0];
+                    let mut right = insn.opnds[1];
+
+                    if let Opnd::Mem(_) = left {
+                        left = asm.load(left);
+                    }
+
+                    match right {
+                        Opnd::Mem(_) => { right = asm.load(right); },
+                        Opnd::UImm(value) if !fits_imm12(value) => { right = asm.load(right); },
+                        Opnd::Imm(value) if !fits_imm12(value as u64) => { right = asm.load(right); },
+                        _ => {}
+                    }
+
+                    insn.opnds[0] = left;
+                    insn.opnds[1] = right;
+                    if insn.op == Op::CmpJcc {
+                        // Expand the fused form back into `Cmp` + `Jcc`.
+                        asm.cmp(left, right);
+                        let jcc_op = match insn.text.as_deref() {
+                            Some("l") => Op::Jl, Some("be") => Op::Jbe, Some("e") => Op::Je, Some("ne") => Op::Jne,
+                            Some("z") => Op::Jz, Some("nz") => Op::Jnz, Some("o") => Op::Jo,
+                            other => unreachable!("unknown CmpJcc condition code {other:?}"),
+                        };
+                        asm.push_insn_parts(jcc_op, vec![], insn.target, None, None);
+                    } else {
+                        asm.push_insn(insn);
+                    }
+                },
+                Op::FCmp => {
+                    let mut left = insn.opnds[0];
+                    let mut right = insn.opnds[1];
+
+                    if !matches!(left, Opnd::Reg(_) | Opnd::InsnOut { .. }) {
+                        left = asm.fload(left);
+                    }
+                    if !matches!(right, Opnd::Reg(_) | Opnd::InsnOut { .. }) {
+                        right = asm.fload(right);
+                    }
+
+                    insn.opnds[0] = left;
+                    insn.opnds[1] = right;
+                    asm.push_insn(insn);
+                },
+                Op::Mov => {
+                    let dest = insn.opnds[0];
+                    let src = insn.opnds[1];
+
+                    // `str`/`stur` can't take an immediate operand, so a
+                    // store of an immediate has to go through a register.
+                    match (dest, src) {
+                        (Opnd::Mem(_), Opnd::UImm(_) | Opnd::Imm(_)) => {
+                            let loaded = asm.load(src);
+                            asm.mov(dest, loaded);
+                        },
+                        _ => {
+                            asm.mov(dest, src);
+                        }
+                    }
+                },
+                Op::Not => {
+                    let opnd = insn.opnds[0];
+                    let opnd0 = match unmapped_opnds[0] {
+                        Opnd::Mem(_) => asm.load(opnd),
+                        _ => opnd,
+                    };
+
+                    asm.not(opnd0);
+                },
+                Op::CCall => {
+                    // AAPCS64 passes only as many arguments in registers as
+                    // `C_ARG_OPNDS` has slots for; anything past that goes
+                    // on the C stack, same as SysV on x86_64. Stack-passed
+                    // arguments don't support the `Opnd::None` in-place
+                    // marker `reorder_c_args` understands below, since
+                    // their final address isn't known until this pass
+                    // decides how deep the stack goes.
+                    let opnds = &insn.opnds;
+                    let (reg_opnds, stack_opnds) = if opnds.len() > C_ARG_OPNDS.len() {
+                        opnds.split_at(C_ARG_OPNDS.len())
+                    } else {
+                        (opnds.as_slice(), &[][..])
+                    };
+
+                    // Shuffle each register operand into its argument
+                    // register, resolving any conflicts between the new
+                    // destinations and the operands' current locations.
+                    asm.reorder_c_args(reg_opnds);
+
+                    // Unlike x86_64, SP-relative loads/stores on this
+                    // platform fault unless SP is 16-byte aligned at the
+                    // time they execute, not just at the call boundary --
+                    // so each stack argument gets its own full 16-byte
+                    // slot (via `cpush` below) instead of SysV's packed
+                    // 8-byte slots with a single parity pad at the end.
+                    let stack_opnds: Vec<Opnd> = stack_opnds.to_vec();
+                    for &opnd in stack_opnds.iter().rev() {
+                        asm.cpush(opnd);
+                    }
+
+                    let fptr = insn.target.unwrap().unwrap_fun_ptr();
+                    let stack_bytes = (stack_opnds.len() * 16) as u64;
+                    if stack_bytes > 0 {
+                        asm.ccall(fptr, vec![Opnd::UImm(stack_bytes)]);
+                    } else {
+                        asm.ccall(fptr, vec![]);
+                    }
+                },
+                Op::CPush => {
+                    let opnd = insn.opnds[0];
+                    // `str` has no immediate-source form, so a pushed
+                    // immediate has to be materialized into a register
+                    // first, the same way `Mov` handles a store of one.
+                    let opnd0 = match unmapped_opnds[0] {
+                        Opnd::UImm(_) | Opnd::Imm(_) | Opnd::Mem(_) => asm.load(opnd),
+                        _ => opnd,
+                    };
+
+                    asm.cpush(opnd0);
+                },
+                Op::GuardHeap => {
+                    // Fails (jumps to the side exit) if the value is an
+                    // immediate, or if it's one of the Qfalse/Qnil
+                    // singletons that sort below every heap pointer.
+                    let opnd = insn.opnds[0];
+                    let target = insn.target.unwrap();
+                    asm.test(opnd, Opnd::UImm(RUBY_IMMEDIATE_MASK as u64));
+                    asm.jnz(target);
+                    asm.cmp(opnd, Opnd::UImm(Qnil.into()));
+                    asm.jbe(target);
+                },
+                Op::GuardImm => {
+                    // The complement of GuardHeap: either check passing
+                    // means the value isn't a heap pointer, so skip past
+                    // the side exit jump instead of taking it.
+                    let opnd = insn.opnds[0];
+                    let target = insn.target.unwrap();
+                    let not_heap = asm.new_label("guard_imm_not_heap");
+                    asm.test(opnd, Opnd::UImm(RUBY_IMMEDIATE_MASK as u64));
+                    asm.jnz(not_heap);
+                    asm.cmp(opnd, Opnd::UImm(Qnil.into()));
+                    asm.jbe(not_heap);
+                    asm.jmp(target);
+                    asm.write_label(not_heap);
+                },
+                Op::GuardFixnum => {
+                    let opnd = insn.opnds[0];
+                    let target = insn.target.unwrap();
+                    asm.test(opnd, Opnd::UImm(RUBY_FIXNUM_FLAG as u64));
+                    asm.jz(target);
+                },
+                Op::JumpTrue => {
+                    // Truthy means neither Qfalse nor Qnil, so skip the
+                    // jump to the target unless both equality checks miss.
+                    let opnd = insn.opnds[0];
+                    let target = insn.target.unwrap();
+                    let falsy = asm.new_label("jump_true_falsy");
+                    asm.cmp(opnd, Opnd::UImm(Qfalse.into()));
+                    asm.je(falsy);
+                    asm.cmp(opnd, Opnd::UImm(Qnil.into()));
+                    asm.je(falsy);
+                    asm.jmp(target);
+                    asm.write_label(falsy);
+                },
+                Op::JumpFalse => {
+                    let opnd = insn.opnds[0];
+                    let target = insn.target.unwrap();
+                    asm.cmp(opnd, Opnd::UImm(Qfalse.into()));
+                    asm.je(target);
+                    asm.cmp(opnd, Opnd::UImm(Qnil.into()));
+                    asm.je(target);
+                },
+                _ => {
+                    if insn.out_opnd().is_some() {
+                        let out_num_bits = Opnd::match_num_bits_iter(insn.opnd_iter());
+                        let out = insn.out_opnd_mut().unwrap();
+                        *out = asm.next_opnd_out(out_num_bits);
+                    }
+
+                    asm.push_insn(insn);
+                }
+            };
+
+            iterator.map_insn_index(&mut asm);
+        }
+
+        asm
+    }
+
+    /// Emit platform-specific machine code for ARM64.
+    pub fn arm64_emit(&mut self, cb: &mut CodeBlock) -> Vec<u32>
+    {
+        // List of GC offsets
+        let mut gc_offsets: Vec<u32> = Vec::new();
+
+        let mut insns_idx: usize = 0;
+        while let Some(insn) = self.insns.get(insns_idx) {
+            let src_ptr = cb.get_write_ptr();
+            let had_dropped_bytes = cb.has_dropped_bytes();
+            let old_label_state = cb.get_label_state();
+
+            match insn.op {
+                Op::Comment => {
+                    if cfg!(feature = "disasm") {
+                        cb.add_comment(insn.text.as_ref().unwrap());
+                    }
+                },
+
+                // Write the label at the current position
+                Op::Label => {
+                    cb.write_label(insn.target.unwrap().unwrap_label_idx());
+                },
+
+                // Report back the current position in the generated code
+                Op::PosMarker => {
+                    let marker = self.insns[insns_idx].pos_marker.take().unwrap();
+                    marker(cb.get_write_ptr());
+                },
+
+                Op::FrameSetup => {},
+                Op::FrameTeardown => {},
+
+                Op::Add => {
+                    add(cb, insn.opnds[0].into(), insn.opnds[1].into());
+                },
+
+                Op::Sub => {
+                    sub(cb, insn.opnds[0].into(), insn.opnds[1].into());
+                },
+
+                Op::And => {
+                    and(cb, insn.opnds[0].into(), insn.opnds[1].into());
+                },
+
+                // `mul` computes the same low 64 bits of the product
+                // whether the inputs are signed or unsigned, so all
+                // three ops lower identically here.
+                Op::Mul | Op::SMul | Op::UMul => {
+                    mul(cb, insn.out.into(), insn.opnds[0].into(), insn.opnds[1].into());
+                },
+
+                Op::SDiv => {
+                    sdiv(cb, insn.out.into(), insn.opnds[0].into(), insn.opnds[1].into());
+                },
+                Op::UDiv => {
+                    udiv(cb, insn.out.into(), insn.opnds[0].into(), insn.opnds[1].into());
+                },
+
+                // ARM64 has no remainder instruction: compute the quotient
+                // into `out` first, then fold `out * right` back out of
+                // `left` with a single fused `msub` (`out = left - out *
+                // right`) instead of a separate multiply and subtract.
+                Op::SMod => {
+                    let (left, right, out) = (insn.opnds[0], insn.opnds[1], insn.out);
+                    sdiv(cb, out.into(), left.into(), right.into());
+                    msub(cb, out.into(), out.into(), right.into(), left.into());
+                },
+                Op::UMod => {
+                    let (left, right, out) = (insn.opnds[0], insn.opnds[1], insn.out);
+                    udiv(cb, out.into(), left.into(), right.into());
+                    msub(cb, out.into(), out.into(), right.into(), left.into());
+                },
+
+                Op::Not => {
+                    mvn(cb, insn.opnds[0].into(), insn.opnds[0].into());
+                },
+
+                Op::Mov => {
+                    mov(cb, insn.opnds[0].into(), insn.opnds[1].into());
+                },
+
+                Op::Load => {
+                    ldr(cb, insn.out.into(), insn.opnds[0].into());
+                },
+
+                Op::Store => {
+                    str(cb, insn.opnds[0].into(), insn.opnds[1].into());
+                },
+
+                Op::FAdd => {
+                    fadd(cb, insn.out.into(), insn.opnds[0].into(), insn.opnds[1].into());
+                },
+                Op::FSub => {
+                    fsub(cb, insn.out.into(), insn.opnds[0].into(), insn.opnds[1].into());
+                },
+                Op::FMul => {
+                    fmul(cb, insn.out.into(), insn.opnds[0].into(), insn.opnds[1].into());
+                },
+                Op::FDiv => {
+                    fdiv(cb, insn.out.into(), insn.opnds[0].into(), insn.opnds[1].into());
+                },
+
+                // `ldr`/`str` take a D-register destination/source the same
+                // way they take a GP one, so `FLoad`/`FStore` reuse them
+                // rather than needing NEON-specific mnemonics.
+                Op::FLoad => {
+                    ldr(cb, insn.out.into(), insn.opnds[0].into());
+                },
+                Op::FStore => {
+                    str(cb, insn.opnds[0].into(), insn.opnds[1].into());
+                },
+
+                // Compare: sets flags the same way `subs` would, without
+                // keeping the result.
+                Op::Cmp => {
+                    cmp(cb, insn.opnds[0].into(), insn.opnds[1].into());
+                },
+
+                // Compare two doubles, sets flags the same way `Cmp` does.
+                Op::FCmp => {
+                    fcmp(cb, insn.opnds[0].into(), insn.opnds[1].into());
+                },
+
+                // Test and set flags, the same way `ands` would.
+                Op::Test => {
+                    tst(cb, insn.opnds[0].into(), insn.opnds[1].into());
+                },
+
+                // Conditional branches rely on flags set by a preceding
+                // Cmp/Test, same as on x86.
+                Op::Jnz => {
+                    match insn.target.unwrap() {
+                        Target::CodePtr(code_ptr) => bcond(cb, Condition::NE, code_ptr),
+                        Target::Label(label_idx) => bcond_label(cb, Condition::NE, label_idx),
+                        Target::FunPtr(_) => unreachable!("Jnz can't target a raw C function pointer"),
+                    }
+                },
+
+                Op::Jbe => {
+                    match insn.target.unwrap() {
+                        Target::CodePtr(code_ptr) => bcond(cb, Condition::LS, code_ptr),
+                        Target::Label(label_idx) => bcond_label(cb, Condition::LS, label_idx),
+                        Target::FunPtr(_) => unreachable!("Jbe can't target a raw C function pointer"),
+                    }
+                },
+
+                Op::CCall => {
+                    let fptr = insn.target.unwrap().unwrap_fun_ptr();
+                    blr_ptr(cb, fptr);
+
+                    // Reclaim whatever `arm64_split` pushed for the
+                    // seventh stack-passed argument onward, restoring SP
+                    // to where it was before those pushes.
+                    if let Some(Opnd::UImm(stack_bytes)) = insn.opnds.first() {
+                        add(cb, A64Opnd::Reg(NATIVE_STACK_PTR_REG), A64Opnd::new_uimm(*stack_bytes));
+                    }
+                },
+
+                // There's no native push/pop on this platform; each slot
+                // gets its own 16-byte-aligned `sub`+`str` (or `ldr`+`add`
+                // to pop), matching the full-slot-per-argument scheme
+                // `arm64_split`'s `CCall` arm lays out above.
+                Op::CPush => {
+                    sub(cb, A64Opnd::Reg(NATIVE_STACK_PTR_REG), A64Opnd::new_uimm(16));
+                    str(cb, insn.opnds[0].into(), A64Opnd::new_mem(A64Opnd::Reg(NATIVE_STACK_PTR_REG), 0));
+                },
+                Op::CPop => {
+                    ldr(cb, insn.out.into(), A64Opnd::new_mem(A64Opnd::Reg(NATIVE_STACK_PTR_REG), 0));
+                    add(cb, A64Opnd::Reg(NATIVE_STACK_PTR_REG), A64Opnd::new_uimm(16));
+                },
+                Op::CPopInto => {
+                    ldr(cb, insn.opnds[0].into(), A64Opnd::new_mem(A64Opnd::Reg(NATIVE_STACK_PTR_REG), 0));
+                    add(cb, A64Opnd::Reg(NATIVE_STACK_PTR_REG), A64Opnd::new_uimm(16));
+                },
+
+                Op::CRet => {
+                    let opnd = insn.opnds[0];
+                    if opnd != Opnd::Reg(C_RET_REG) {
+                        mov(cb, A64Opnd::Reg(C_RET_REG), opnd.into());
+                    }
+
+                    ret(cb);
+                },
+
+                Op::LiveReg => (), // just a reg alloc signal, no code
+
+                // We want to keep the panic here because some instructions
+                // that we feed to the backend could get lowered into other
+                // instructions by arm64_split. So it's possible that some
+                // of our backend instructions can never make it to the
+                // emit stage, or haven't been ported to this backend yet.
+                _ => panic!("unsupported instruction passed to arm64 backend: {:?}", insn)
+            };
+
+            if !had_dropped_bytes && cb.has_dropped_bytes() && cb.next_page(src_ptr, |cb, code_ptr| bcond(cb, Condition::AL, code_ptr)) {
+                // Reset cb states before retrying the current Insn
+                cb.set_label_state(old_label_state);
+            } else {
+                insns_idx += 1;
+            }
+        }
+
+        gc_offsets
+    }
+
+    /// Run the full lowering pipeline (generic peephole and splitting,
+    /// platform splitting, register allocation) and compile the stored
+    /// instructions for the ARM64 platform.
+    pub fn compile_with_regs(self, cb: &mut CodeBlock, regs: Vec<Reg>) -> (CodePtr, Vec<u32>)
+    {
+        let mut asm = self.peephole().split_insns().arm64_split().alloc_regs(regs);
+
+        // Create label instances in the code block
+        for (idx, name) in asm.label_names.iter().enumerate() {
+            let label_idx = cb.new_label(name.to_string());
+            assert!(label_idx == idx);
+        }
+
+        let start_ptr = cb.get_write_ptr();
+        let gc_offsets = asm.arm64_emit(cb);
+
+        if cb.has_dropped_bytes() {
+            cb.clear_labels();
+        } else {
+            cb.link_labels();
+        }
+
+        (start_ptr, gc_offsets)
+    }
+}
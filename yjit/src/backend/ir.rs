@@ -3,10 +3,12 @@
 #![allow(unused_imports)]
 
 use std::cell::Cell;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt;
 use std::convert::From;
 use std::mem::take;
-use crate::cruby::{VALUE};
+use crate::cruby::{VALUE, Qnil, Qfalse, RUBY_IMMEDIATE_MASK, RUBY_FIXNUM_FLAG};
 use crate::virtualmem::{CodePtr};
 use crate::asm::{CodeBlock, uimm_num_bits, imm_num_bits};
 use crate::core::{Context, Type, TempMapping};
@@ -81,6 +83,84 @@ pub enum Op
     /// Shift a value left by a certain amount.
     LShift,
 
+    // Truncating two-operand signed multiply: `out` gets the low 64 bits
+    // of `left * right`, same shape as `Add`. Lowers to a single x86
+    // `IMUL r64, r/m64` that doesn't touch RDX (on arm64, a plain `mul`),
+    // for multiplies where the caller doesn't need to check for overflow.
+    Mul,
+
+    // Full-width signed multiply: on x86_64 this lowers to the one-operand
+    // `IMUL r/m64`, which implicitly multiplies RAX by `right` and leaves
+    // the low 64 bits of the 128-bit product in RAX (the high half in
+    // RDX); `left` is pinned to RAX by `x86_split`, the same
+    // implicit-register treatment `CmpXchg` gives its comparand. On
+    // arm64, which has no implicit operands, this lowers to a plain
+    // `mul` and is indistinguishable from `Mul`/`UMul`.
+    SMul,
+
+    // Unsigned counterpart of `SMul`, lowering to the one-operand `MUL
+    // r/m64` on x86_64.
+    UMul,
+
+    // Signed divide: on x86_64 this lowers to `IDIV r/m64`, which
+    // implicitly divides the 128-bit value in RDX:RAX by `right`, leaving
+    // the quotient in RAX and the remainder in RDX; `x86_split` pins
+    // `left` to RAX and sign-extends it into RDX ahead of the
+    // instruction. On arm64, a plain `sdiv` with fully explicit operands.
+    SDiv,
+
+    // Unsigned divide: on x86_64 this lowers to `DIV r/m64`, like `SDiv`
+    // but `x86_split` zeroes RDX instead of sign-extending it. On arm64,
+    // a plain `udiv`.
+    UDiv,
+
+    // Signed remainder: on x86_64, the same `IDIV` as `SDiv`, but `out`
+    // is pinned to RDX (the remainder) instead of RAX (the quotient). On
+    // arm64, which has no remainder instruction, lowers to `sdiv` for the
+    // quotient followed by a fused `msub` to fold it back out of the
+    // dividend.
+    SMod,
+
+    // Unsigned remainder: same shape as `SMod`, but built on `UDiv`/`udiv`
+    // instead of `SDiv`/`sdiv`.
+    UMod,
+
+    // Add two floating-point operands and return the result as a new
+    // operand, the same shape as `Add` but lowered to an SSE2
+    // scalar-double `addsd` over the XMM register class on x86_64, or
+    // `fadd` over the D register class on arm64, instead of the
+    // general-purpose one.
+    FAdd,
+
+    // Floating-point counterpart of `Sub`, lowered to `subsd` (x86_64) or
+    // `fsub` (arm64).
+    FSub,
+
+    // Floating-point counterpart that multiplies two operands, lowered to
+    // `mulsd` (x86_64) or `fmul` (arm64).
+    FMul,
+
+    // Floating-point counterpart that divides the first operand by the
+    // second, lowered to `divsd` (x86_64) or `fdiv` (arm64).
+    FDiv,
+
+    // Load a double out of memory into a float register, lowered to
+    // `movsd` (x86_64) or `ldr` (arm64, which uses the same load
+    // instruction for its D registers as it does for GP ones). The
+    // floating-point counterpart of `Load`.
+    FLoad,
+
+    // Store a double from a float register into memory, lowered to
+    // `movsd` (x86_64) or `str` (arm64). The floating-point counterpart
+    // of `Store`.
+    FStore,
+
+    // Compare two doubles, lowered to `ucomisd` (x86_64) or `fcmp` (arm64).
+    // The floating-point counterpart of `Cmp`: it has no `out` of its own
+    // and is only useful immediately before a `CSel*`/`Jcc` that reads the
+    // flags it sets.
+    FCmp,
+
     //
     // Low-level instructions
     //
@@ -126,6 +206,34 @@ pub enum Op
     Jnz,
     Jo,
 
+    // Fused `Cmp` immediately followed by a conditional jump, produced by
+    // the peephole pass. There's no field to carry the condition code, so
+    // it's stashed in `text` ("l", "be", "e", "ne", "z", "nz", or "o");
+    // the platform splitting pass expands this back into a plain `Cmp`
+    // and the matching low-level `Jcc` above.
+    CmpJcc,
+
+    // Guard that a VALUE operand is a heap pointer (neither an immediate
+    // nor one of the false/nil singletons), jumping to the target on
+    // failure. Fuses the test-mask-then-compare pattern every type check
+    // in codegen would otherwise hand write.
+    GuardHeap,
+
+    // Guard that a VALUE operand is an immediate (the complement of
+    // GuardHeap), jumping to the target on failure.
+    GuardImm,
+
+    // Guard that a VALUE operand is a fixnum, jumping to the target on
+    // failure.
+    GuardFixnum,
+
+    // Ruby-truthy/falsy conditional jumps. Unlike Jz/Jnz these encode
+    // `RTEST` (anything other than Qfalse/Qnil is truthy), not a simple
+    // zero test, so they accept a VALUE operand directly rather than
+    // relying on flags set by a preceding Cmp/Test.
+    JumpTrue,
+    JumpFalse,
+
     // Conditional select instructions
     CSelZ,
     CSelNZ,
@@ -157,6 +265,44 @@ pub enum Op
     // Produces no output
     IncrCounter,
 
+    // Atomic fetch-and-add: lowers to `LOCK XADD mem, reg` on x86-64.
+    // Input: memory operand, value to add. Returns the value that was in
+    // memory before the add, for use cases like incrementing a shared
+    // profiling counter and reading back the pre-increment count.
+    AtomicAdd,
+
+    // Atomic compare-and-swap: lowers to `LOCK CMPXCHG mem, reg` on
+    // x86-64, which implicitly compares against and conditionally
+    // overwrites RAX. Input: memory operand, expected value, desired
+    // value. Returns the value that was actually in memory (RAX after the
+    // instruction), so a caller can tell success (`out == expected`) from
+    // failure apart from checking flags directly. `x86_split` pins the
+    // expected operand to RAX the same way it pins `CCall` arguments
+    // to the C ABI registers, and `x86_emit` copies RAX into `out` after
+    // the instruction. Used for lock-free state transitions that
+    // don't warrant a full `CPushAll`/C-call round trip.
+    CmpXchg,
+
+    // Atomic fetch-and-subtract: `val`'s register is negated in place and
+    // fed through the same `LOCK XADD` `AtomicAdd` uses, since x86-64 has
+    // no dedicated atomic-subtract encoding. Input: memory operand, value
+    // to subtract. Returns the value that was in memory before the
+    // subtract.
+    AtomicSub,
+
+    // Atomic exchange: lowers to `XCHG mem, reg`, which is implicitly
+    // locked and needs no explicit LOCK prefix. Input: memory operand,
+    // new value. Returns the value that was in memory beforehand.
+    AtomicXchg,
+
+    // Atomic compare-and-swap that reports success/failure directly
+    // rather than the old value: otherwise identical to `CmpXchg`,
+    // including pinning `expected` to RAX in `x86_split`, but `out` is a
+    // materialized 0/1 (from ZF) instead of the memory's prior contents.
+    // For callers that only care whether the swap took, without a
+    // separate compare against `expected`.
+    AtomicCmpXchg,
+
     // Trigger a debugger breakpoint
     Breakpoint,
 
@@ -387,7 +533,12 @@ impl From<CodePtr> for Target {
     }
 }
 
-type PosMarkerFn = Box<dyn Fn(CodePtr)>;
+// A marker fires exactly once, when the position it was built with becomes
+// known, so it's stored as `FnOnce` rather than `Fn` -- this lets a caller
+// move owned data (e.g. a collected branch record) into the closure instead
+// of routing it through an `Rc<RefCell<...>>` just to share it past the
+// call that registers the marker.
+type PosMarkerFn = Box<dyn FnOnce(CodePtr)>;
 
 /// YJIT IR instruction
 pub struct Insn
@@ -412,6 +563,32 @@ pub struct Insn
     pub(super) pos_marker: Option<PosMarkerFn>,
 }
 
+impl Insn {
+    /// Iterate over all of this instruction's input operands, regardless
+    /// of what `op` it carries -- used by the generic splitting/peephole
+    /// passes that need to remap every operand without caring about its
+    /// positional meaning.
+    pub(super) fn opnd_iter(&self) -> std::slice::Iter<Opnd> {
+        self.opnds.iter()
+    }
+
+    /// Mutable counterpart of `opnd_iter`.
+    pub(super) fn opnd_iter_mut(&mut self) -> std::slice::IterMut<Opnd> {
+        self.opnds.iter_mut()
+    }
+
+    /// This instruction's output operand, or `None` if `op` doesn't
+    /// produce a value (see `produces_value`).
+    pub(super) fn out_opnd(&self) -> Option<&Opnd> {
+        if produces_value(self.op) { Some(&self.out) } else { None }
+    }
+
+    /// Mutable counterpart of `out_opnd`.
+    pub(super) fn out_opnd_mut(&mut self) -> Option<&mut Opnd> {
+        if produces_value(self.op) { Some(&mut self.out) } else { None }
+    }
+}
+
 impl fmt::Debug for Insn {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         write!(fmt, "{:?}(", self.op)?;
@@ -450,6 +627,37 @@ pub struct Assembler
 
     /// Names of labels
     pub(super) label_names: Vec<String>,
+
+    /// Free list of displacements (from `NATIVE_STACK_PTR_REG`) of spill
+    /// slots that `alloc_regs` has reclaimed because their value died.
+    /// Reused before growing the frame with a new slot. Only meaningful
+    /// while `alloc_regs` is actively building this `Assembler`; every
+    /// other pass leaves it empty.
+    pub(super) spill_slots: Vec<i32>,
+
+    /// Displacement to hand out for the next spill slot that can't be
+    /// satisfied from `spill_slots`.
+    pub(super) next_spill_disp: i32,
+}
+
+// Whether `op`'s builder returns an `Opnd` that's meaningful to reference
+// from a later instruction's operands, as opposed to one of the many ops
+// (`Cmp`, `Store`, `Jmp`, ...) whose builder returns `()` -- every `Insn`
+// gets an `Opnd::InsnOut` in `out` once `push_insn` runs regardless of
+// `op`, so this can't be read off the instruction itself and has to be
+// classified by op instead. Shared by `Assembler::verify` and the
+// `to_text`/`parse` pair below, which both need to know whether an
+// instruction gets a `%N =` of its own.
+fn produces_value(op: Op) -> bool {
+    !matches!(
+        op,
+        Op::Comment | Op::Label | Op::PosMarker | Op::BakeString |
+        Op::Mov | Op::Store | Op::FStore | Op::Test | Op::Cmp | Op::CmpJcc |
+        Op::Jmp | Op::JmpOpnd | Op::Jl | Op::Jbe | Op::Je | Op::Jne | Op::Jz | Op::Jnz | Op::Jo |
+        Op::GuardHeap | Op::GuardImm | Op::GuardFixnum | Op::JumpTrue | Op::JumpFalse |
+        Op::CPush | Op::CPopInto | Op::CPushAll | Op::CPopAll | Op::CRet |
+        Op::IncrCounter | Op::Breakpoint | Op::FrameSetup | Op::FrameTeardown | Op::FCmp
+    )
 }
 
 impl Assembler
@@ -462,7 +670,9 @@ impl Assembler
         Self {
             insns: Vec::default(),
             live_ranges: Vec::default(),
-            label_names
+            label_names,
+            spill_slots: Vec::default(),
+            next_spill_disp: 8,
         }
     }
 
@@ -570,57 +780,167 @@ impl Assembler
     /// Sets the out field on the various instructions that require allocated
     /// registers because their output is used as the operand on a subsequent
     /// instruction. This is our implementation of the linear scan algorithm.
+    ///
+    /// Liveness comes from `self.live_ranges`, which already holds, for
+    /// every instruction index, the index of its last use -- the same
+    /// information a dedicated backward scan over the finished instruction
+    /// list would produce (an index's interval starts at its own position
+    /// and ends at the last later instruction that references it as an
+    /// `InsnOut`), just computed incrementally as `push_insn` builds the
+    /// list rather than in a separate pass afterward. Walking instructions
+    /// index-by-index here plays the role of linear scan's "increasing
+    /// start point" order: the two `RegPool`s (general-purpose,
+    /// floating-point) each track which interval currently owns every
+    /// physical register, hand out the first free one, and when every
+    /// register in the pool an output needs is taken, `choose_spill_victim`
+    /// evicts whichever active interval ends latest -- the interval least
+    /// likely to be needed again soon.
+    ///
+    /// When the pool of physical registers is exhausted, the value with the
+    /// latest-ending live range is spilled to a stack slot (see
+    /// `spill_slots` below): the defining instruction's `out` is rewritten
+    /// to that slot and later uses read the memory operand directly. No
+    /// reload into a register is inserted, so this never fails on register
+    /// pressure. `CCall` and
+    /// the one-operand `idiv`/`mul` family (`SMul`/`UMul`/`SDiv`/`UDiv`/
+    /// `SMod`/`UMod`) additionally force every register both pools are
+    /// still holding onto to spill ahead of them, since those clobber the
+    /// full caller-saved set (`CCall`) or the implicit RAX:RDX pair
+    /// (`x86_split` pins `left`/`out` there) regardless of what this pass
+    /// would otherwise have assigned; `CRet`'s matching constraint is
+    /// instead enforced down in `x86_emit`/`arm64_emit`, which insert a
+    /// `mov` into `C_RET_REG` if the value isn't already there.
     pub(super) fn alloc_regs(mut self, regs: Vec<Reg>) -> Assembler
     {
         //dbg!(&self);
 
-        // First, create the pool of registers.
-        let mut pool: u32 = 0;
+        // Whether this op's output belongs to the floating-point register
+        // class instead of the general-purpose one.
+        fn is_fp_op(op: Op) -> bool {
+            matches!(op, Op::FAdd | Op::FSub | Op::FMul | Op::FDiv | Op::FLoad)
+        }
 
-        // Mutate the pool bitmap to indicate that the register at that index
-        // has been allocated and is live.
-        fn alloc_reg(pool: &mut u32, regs: &Vec<Reg>) -> Reg {
-            for (index, reg) in regs.iter().enumerate() {
-                if (*pool & (1 << index)) == 0 {
-                    *pool |= 1 << index;
-                    return *reg;
-                }
+        // One independently-tracked class of allocatable registers --
+        // general-purpose or floating-point -- so spilling a live value in
+        // one class never evicts a live value in the other. `store_op` is
+        // the low-level store this class's values spill through (`Store`'s
+        // plain `mov` for general-purpose registers, `FStore`'s `movsd` for
+        // XMM ones), since the two aren't interchangeable on the stack.
+        struct RegPool {
+            regs: Vec<Reg>,
+            pool: u32,
+            // Index (in `regs`) -> the old instruction index whose value
+            // currently occupies that physical register, if any. Used to
+            // pick a spill victim.
+            reg_owner: Vec<Option<usize>>,
+            store_op: Op,
+        }
+
+        impl RegPool {
+            fn new(regs: Vec<Reg>, store_op: Op) -> Self {
+                let reg_owner = vec![None; regs.len()];
+                Self { regs, pool: 0, reg_owner, store_op }
             }
 
-            unreachable!("Register spill not supported");
-        }
+            fn index_of(&self, reg: &Reg) -> Option<usize> {
+                self.regs.iter().position(|elem| elem.reg_no == reg.reg_no)
+            }
+
+            fn owns(&self, reg: &Reg) -> bool {
+                self.index_of(reg).is_some()
+            }
 
-        // Allocate a specific register
-        fn take_reg(pool: &mut u32, regs: &Vec<Reg>, reg: &Reg) -> Reg {
-            let reg_index = regs.iter().position(|elem| elem.reg_no == reg.reg_no);
+            // Allocate a specific register
+            fn take_reg(&mut self, reg: &Reg) -> Reg {
+                if let Some(reg_index) = self.index_of(reg) {
+                    assert_eq!(self.pool & (1 << reg_index), 0, "register already allocated");
+                    self.pool |= 1 << reg_index;
+                }
+
+                *reg
+            }
+
+            // Mutate the pool bitmap to indicate that the given register is
+            // being returned as it is no longer used by the instruction that
+            // previously held it.
+            fn dealloc_reg(&mut self, reg: &Reg) {
+                if let Some(reg_index) = self.index_of(reg) {
+                    self.pool &= !(1 << reg_index);
+                    self.reg_owner[reg_index] = None;
+                }
+            }
 
-            if let Some(reg_index) = reg_index {
-                assert_eq!(*pool & (1 << reg_index), 0, "register already allocated");
-                *pool |= 1 << reg_index;
+            // Picks the live register whose value is used farthest in the
+            // future (or never again, for insns kept alive only by later
+            // instructions that have yet to run), to minimize how soon it
+            // has to be reloaded.
+            fn choose_spill_victim(&self, live_ranges: &[usize]) -> usize {
+                self.reg_owner
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(reg_index, owner)| owner.map(|owner| (reg_index, live_ranges[owner])))
+                    .max_by_key(|&(_, end)| end)
+                    .map(|(reg_index, _)| reg_index)
+                    .expect("no live registers to spill")
             }
+        }
 
-            return *reg;
+        // Picks whichever pool currently owns `reg`. Dealloc/spill
+        // decisions are driven by the register a *previous* instruction's
+        // output landed in, not by the current instruction's own class.
+        fn pool_for_reg<'a>(gp: &'a mut RegPool, fp: &'a mut RegPool, reg: &Reg) -> &'a mut RegPool {
+            if fp.owns(reg) { fp } else { gp }
         }
 
-        // Mutate the pool bitmap to indicate that the given register is being
-        // returned as it is no longer used by the instruction that previously
-        // held it.
-        fn dealloc_reg(pool: &mut u32, regs: &Vec<Reg>, reg: &Reg) {
-            let reg_index = regs.iter().position(|elem| elem.reg_no == reg.reg_no);
+        fn alloc_spill_slot(asm: &mut Assembler) -> i32 {
+            asm.spill_slots.pop().unwrap_or_else(|| {
+                let disp = asm.next_spill_disp;
+                asm.next_spill_disp += 8;
+                disp
+            })
+        }
 
-            if let Some(reg_index) = reg_index {
-                *pool &= !(1 << reg_index);
-            }
+        // Spills the value currently held in `pool.regs[reg_index]` to a
+        // stack slot, rewriting its defining instruction's `out` so every
+        // later reference to it resolves to memory instead. Leaves the pool
+        // bit for that register untouched; callers decide whether it's being
+        // freed outright or immediately handed to a new owner.
+        fn spill_reg(
+            pool: &mut RegPool,
+            reg_index: usize,
+            index_map: &[usize],
+            asm: &mut Assembler,
+        ) {
+            let victim_reg = pool.regs[reg_index];
+            let victim_owner = pool.reg_owner[reg_index].expect("spill_reg called on an unoccupied register");
+
+            let disp = alloc_spill_slot(asm);
+            let spill_mem = Opnd::Mem(Mem { base: MemBase::Reg(NATIVE_STACK_PTR_REG.reg_no), disp, num_bits: 64 });
+
+            asm.push_insn_parts(pool.store_op, vec![spill_mem, Opnd::Reg(victim_reg)], None, None, None);
+            asm.insns[index_map[victim_owner]].out = spill_mem;
+
+            pool.reg_owner[reg_index] = None;
         }
 
+        let mut gp_pool = RegPool::new(regs, Op::Store);
+        let mut fp_pool = RegPool::new(Self::get_fp_alloc_regs(), Op::FStore);
+
         let live_ranges: Vec<usize> = take(&mut self.live_ranges);
         let mut asm = Assembler::new_with_label_names(take(&mut self.label_names));
+
+        // Maps an old instruction index to the index of the (non-spill-store)
+        // instruction that implements it in `asm.insns`. Needed because
+        // spilling can insert extra `Store`/`FStore` instructions ahead of an
+        // insn, which would otherwise desync old and new indices.
+        let mut index_map: Vec<usize> = Vec::new();
+
         let mut iterator = self.into_draining_iter();
 
         while let Some((index, insn)) = iterator.next_unmapped() {
             // Check if this is the last instruction that uses an operand that
             // spans more than one instruction. In that case, return the
-            // allocated register to the pool.
+            // allocated register (or spill slot) to the pool.
             for opnd in &insn.opnds {
                 match opnd {
                     Opnd::InsnOut{idx, .. } |
@@ -632,12 +952,16 @@ impl Assembler
 
                         // We're going to check if this is the last instruction that
                         // uses this operand. If it is, we can return the allocated
-                        // register to the pool.
+                        // register or spill slot to the pool.
                         if live_ranges[start_index] == index {
-                            if let Opnd::Reg(reg) = asm.insns[start_index].out {
-                                dealloc_reg(&mut pool, &regs, &reg);
-                            } else {
-                                unreachable!("no register allocated for insn {:?}", insn.op);
+                            match asm.insns[index_map[start_index]].out {
+                                Opnd::Reg(reg) => {
+                                    pool_for_reg(&mut gp_pool, &mut fp_pool, &reg).dealloc_reg(&reg);
+                                },
+                                Opnd::Mem(Mem { disp, .. }) => {
+                                    asm.spill_slots.push(disp);
+                                },
+                                _ => unreachable!("no register allocated for insn {:?}", insn.op),
                             }
                         }
                     }
@@ -646,9 +970,25 @@ impl Assembler
                 }
             }
 
-            // C return values need to be mapped to the C return register
-            if insn.op == Op::CCall {
-                assert_eq!(pool, 0, "register lives past C function call");
+            // A C call clobbers every caller-saved register in both
+            // classes, so any value either allocator is still holding onto
+            // has to be spilled to a stack slot ahead of the call and freed
+            // back to its pool; it will be read back out of memory by
+            // whichever later instruction needs it (see the InsnOut-to-Mem
+            // rewrite above). The one-operand `imul`/`mul`/`idiv`/`div`
+            // forms behind `SMul`/`UMul`/`SDiv`/`UDiv`/`SMod`/`UMod`
+            // implicitly read and/or overwrite RDX:RAX the same way, so
+            // they get the same treatment even though they don't clobber
+            // the rest of the caller-saved set.
+            if matches!(insn.op, Op::CCall | Op::SMul | Op::UMul | Op::SDiv | Op::UDiv | Op::SMod | Op::UMod) {
+                for pool in [&mut gp_pool, &mut fp_pool] {
+                    for reg_index in 0..pool.regs.len() {
+                        if pool.reg_owner[reg_index].is_some() {
+                            spill_reg(pool, reg_index, &index_map, &mut asm);
+                            pool.pool &= !(1 << reg_index);
+                        }
+                    }
+                }
             }
 
             // If this instruction is used by another instruction,
@@ -658,7 +998,19 @@ impl Assembler
 
                 // C return values need to be mapped to the C return register
                 if insn.op == Op::CCall {
-                    out_reg = Opnd::Reg(take_reg(&mut pool, &regs, &C_RET_REG))
+                    out_reg = Opnd::Reg(gp_pool.take_reg(&C_RET_REG))
+                }
+
+                // The quotient/product lands in `MULDIV_OUT_REG`, the
+                // remainder in `MULDIV_REM_REG` -- on x86_64 these are RAX
+                // and RDX, which `x86_split` already pins the implicit
+                // operands to match; arm64 has no such constraint but still
+                // routes the result through the same pair of names.
+                else if matches!(insn.op, Op::SMul | Op::UMul | Op::SDiv | Op::UDiv) {
+                    out_reg = Opnd::Reg(gp_pool.take_reg(&MULDIV_OUT_REG))
+                }
+                else if matches!(insn.op, Op::SMod | Op::UMod) {
+                    out_reg = Opnd::Reg(gp_pool.take_reg(&MULDIV_REM_REG))
                 }
 
                 // If this instruction's first operand maps to a register and
@@ -669,31 +1021,46 @@ impl Assembler
                 else if insn.opnds.len() > 0 {
                     if let Opnd::InsnOut{idx, ..} = insn.opnds[0] {
                         if live_ranges[idx] == index {
-                            if let Opnd::Reg(reg) = asm.insns[idx].out {
-                                out_reg = Opnd::Reg(take_reg(&mut pool, &regs, &reg))
+                            if let Opnd::Reg(reg) = asm.insns[index_map[idx]].out {
+                                out_reg = Opnd::Reg(pool_for_reg(&mut gp_pool, &mut fp_pool, &reg).take_reg(&reg))
                             }
                         }
                     }
                 }
 
-                // Allocate a new register for this instruction
+                // Allocate a new register for this instruction, spilling a
+                // currently-live value to a stack slot if the pool is full.
                 if out_reg == Opnd::None {
+                    let pool = if is_fp_op(insn.op) { &mut fp_pool } else { &mut gp_pool };
+
                     out_reg = if insn.op == Op::LiveReg {
                         // Allocate a specific register
                         let reg = insn.opnds[0].unwrap_reg();
-                        Opnd::Reg(take_reg(&mut pool, &regs, &reg))
+                        Opnd::Reg(pool.take_reg(&reg))
+                    } else if let Some(reg_index) = (0..pool.regs.len()).find(|i| pool.pool & (1 << i) == 0) {
+                        pool.pool |= 1 << reg_index;
+                        Opnd::Reg(pool.regs[reg_index])
                     } else {
-                        Opnd::Reg(alloc_reg(&mut pool, &regs))
+                        let victim_index = pool.choose_spill_victim(&live_ranges);
+                        let victim_reg = pool.regs[victim_index];
+
+                        // Spill the victim's value to its new stack slot
+                        // ahead of the current instruction, then repurpose
+                        // its register for this instruction's output.
+                        spill_reg(pool, victim_index, &index_map, &mut asm);
+
+                        Opnd::Reg(victim_reg)
                     }
                 }
             }
 
-            // Replace InsnOut operands by their corresponding register
+            // Replace InsnOut operands by their corresponding register or
+            // spill slot.
             let reg_opnds: Vec<Opnd> = insn.opnds.into_iter().map(|opnd|
                 match opnd {
-                    Opnd::InsnOut{idx, ..} => asm.insns[idx].out,
+                    Opnd::InsnOut{idx, ..} => asm.insns[index_map[idx]].out,
                     Opnd::Mem(Mem { base: MemBase::InsnOut(idx), disp, num_bits }) => {
-                        let out_reg = asm.insns[idx].out.unwrap_reg();
+                        let out_reg = asm.insns[index_map[idx]].out.unwrap_reg();
                         Opnd::Mem(Mem {
                             base: MemBase::Reg(out_reg.reg_no),
                             disp,
@@ -705,38 +1072,783 @@ impl Assembler
             ).collect();
 
             asm.push_insn_parts(insn.op, reg_opnds, insn.target, insn.text, insn.pos_marker);
+            index_map.push(asm.insns.len() - 1);
 
             // Set the output register for this instruction
             let num_insns = asm.insns.len();
             let mut new_insn = &mut asm.insns[num_insns - 1];
             if let Opnd::Reg(reg) = out_reg {
                 let num_out_bits = new_insn.out.rm_num_bits();
-                out_reg = Opnd::Reg(reg.sub_reg(num_out_bits))
+                out_reg = Opnd::Reg(reg.sub_reg(num_out_bits));
+
+                if let Opnd::Reg(reg) = out_reg {
+                    let pool = pool_for_reg(&mut gp_pool, &mut fp_pool, &reg);
+                    if let Some(reg_index) = pool.index_of(&reg) {
+                        pool.reg_owner[reg_index] = Some(index);
+                    }
+                }
             }
             new_insn.out = out_reg;
         }
 
-        assert_eq!(pool, 0, "Expected all registers to be returned to the pool");
+        assert_eq!(gp_pool.pool, 0, "Expected all general-purpose registers to be returned to the pool");
+        assert_eq!(fp_pool.pool, 0, "Expected all floating-point registers to be returned to the pool");
         asm
     }
 
-    /// Compile the instructions down to machine code
+    /// Compile the instructions down to machine code.
+    /// Runs the full lowering pipeline (peephole optimization, instruction
+    /// splitting, register allocation, then platform-specific emission) and
+    /// returns the entry `CodePtr` of the generated code along with its GC
+    /// offsets.
     /// NOTE: should compile return a list of block labels to enable
     ///       compiling multiple blocks at a time?
-    pub fn compile(self, cb: &mut CodeBlock) -> Vec<u32>
+    pub fn compile(self, cb: &mut CodeBlock) -> (CodePtr, Vec<u32>)
     {
         let alloc_regs = Self::get_alloc_regs();
         self.compile_with_regs(cb, alloc_regs)
     }
 
+    /// Check that every instruction is well-formed: the right number of
+    /// operands for its op, a `target` where (and only where) the op needs
+    /// one, a `text`/`pos_marker` wherever the op needs one (an unneeded
+    /// one is not reported), and no operand referencing an instruction
+    /// that doesn't come before it or that never produces a usable output
+    /// (e.g. a `Cmp` or `Jmp`, whose builders return `()` rather than an
+    /// `Opnd`). A cheap self-check backend authors can run in debug builds
+    /// before handing a program off to `compile`, so a mis-constructed
+    /// `Insn` shows up as a readable message here instead of a
+    /// wrong-register crash deep in `split_insns`/`alloc_regs`.
+    pub fn verify(&self) -> Result<(), Vec<(usize, String)>> {
+        // (expected operand count, `None` meaning variadic -- only `CCall`
+        // takes one -- whether a `target` is required, whether `text` is
+        // required, whether a `pos_marker` is required) for the ops the
+        // request calls out by name plus their closest relatives; anything
+        // not listed here is only checked for dangling/non-value operand
+        // references below, not arity.
+        fn shape(op: Op) -> Option<(Option<usize>, bool, bool, bool)> {
+            Some(match op {
+                Op::Comment | Op::BakeString => (Some(0), false, true, false),
+                Op::Label => (Some(0), true, false, false),
+                Op::PosMarker => (Some(0), false, false, true),
+                Op::Add | Op::Sub | Op::And | Op::Or | Op::Xor |
+                Op::FAdd | Op::FSub | Op::FMul | Op::FDiv | Op::FCmp |
+                Op::LShift | Op::RShift | Op::URShift |
+                Op::Mul | Op::SMul | Op::UMul | Op::SDiv | Op::UDiv | Op::SMod | Op::UMod |
+                Op::Mov | Op::Store | Op::FStore | Op::Test | Op::Cmp |
+                Op::AtomicAdd | Op::AtomicSub | Op::AtomicXchg | Op::IncrCounter |
+                Op::CSelE | Op::CSelNE | Op::CSelZ | Op::CSelNZ |
+                Op::CSelL | Op::CSelLE | Op::CSelG | Op::CSelGE => (Some(2), false, false, false),
+                Op::CmpXchg | Op::AtomicCmpXchg => (Some(3), false, false, false),
+                Op::Not | Op::Load | Op::LoadSExt | Op::FLoad | Op::Lea | Op::JmpOpnd | Op::LiveReg => (Some(1), false, false, false),
+                Op::GuardHeap | Op::GuardImm | Op::GuardFixnum | Op::JumpTrue | Op::JumpFalse => (Some(1), true, false, false),
+                Op::Jmp | Op::Jl | Op::Jbe | Op::Je | Op::Jne | Op::Jz | Op::Jnz | Op::Jo | Op::LeaLabel => (Some(0), true, false, false),
+                Op::CmpJcc => (Some(2), true, true, false),
+                Op::CPush | Op::CPopInto | Op::CRet => (Some(1), false, false, false),
+                Op::CPop | Op::CPushAll | Op::CPopAll | Op::Breakpoint | Op::FrameSetup | Op::FrameTeardown => (Some(0), false, false, false),
+                Op::CCall => (None, true, false, false),
+                _ => return None,
+            })
+        }
+
+        let mut errors: Vec<(usize, String)> = Vec::new();
+
+        for (index, insn) in self.insns.iter().enumerate() {
+            if let Some((expected_opnds, wants_target, wants_text, wants_pos_marker)) = shape(insn.op) {
+                if let Some(expected) = expected_opnds {
+                    if insn.opnds.len() != expected {
+                        errors.push((index, format!("{:?} expects {expected} operand(s), found {}", insn.op, insn.opnds.len())));
+                    }
+                }
+
+                if wants_target != insn.target.is_some() {
+                    errors.push((index, format!("{:?} {} a target", insn.op, if wants_target { "requires" } else { "shouldn't carry" })));
+                }
+
+                if wants_text && insn.text.is_none() {
+                    errors.push((index, format!("{:?} requires text", insn.op)));
+                }
+
+                if wants_pos_marker && insn.pos_marker.is_none() {
+                    errors.push((index, format!("{:?} requires a pos_marker", insn.op)));
+                }
+            }
+
+            for opnd in &insn.opnds {
+                let producer = match opnd {
+                    Opnd::InsnOut { idx, .. } => Some(*idx),
+                    Opnd::Mem(Mem { base: MemBase::InsnOut(idx), .. }) => Some(*idx),
+                    _ => None,
+                };
+
+                if let Some(producer) = producer {
+                    if producer >= index {
+                        errors.push((index, format!("operand references instruction {producer}, which doesn't come before it")));
+                    } else if !produces_value(self.insns[producer].op) {
+                        errors.push((index, format!("operand references instruction {producer} ({:?}), which produces no usable output", self.insns[producer].op)));
+                    }
+                }
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /// Render this `Assembler`'s instructions as a compact, line-oriented
+    /// text format that `Assembler::parse` can read back: one instruction
+    /// per line, `%N = Op opnd, opnd -> target ; text`, with the `%N =`
+    /// prefix only on lines whose op `produces_value`, and the `-> target`/
+    /// `; text` suffixes only when the instruction actually carries one.
+    /// Operands round-trip as `%N` (a prior instruction's output), `+0xH`/
+    /// `-0xH` (a signed `Imm`), `0xH` (an unsigned `UImm`), `regB_N` (a
+    /// `B`-bit register numbered `N`), `mB[base+-disp]` (a `B`-bit `Mem`,
+    /// `base` either `rN` or `%N`), or `none`. Unlike the `fmt::Debug` impl
+    /// above, a `Value` operand and a `CodePtr`/`FunPtr` target only render
+    /// as an opaque placeholder -- `parse` rejects them -- since neither
+    /// carries enough in this text form to reconstruct the original Ruby
+    /// value or code address.
+    pub fn to_text(&self) -> String {
+        fn render_target(target: Target) -> String {
+            match target {
+                Target::Label(idx) => format!("label_{idx}"),
+                Target::CodePtr(ptr) => format!("codeptr({ptr:?})"),
+                Target::FunPtr(ptr) => format!("funptr({ptr:?})"),
+            }
+        }
+
+        fn render_opnd(opnd: Opnd) -> String {
+            match opnd {
+                Opnd::None => "none".to_string(),
+                Opnd::Value(val) => format!("value({val:?})"),
+                Opnd::InsnOut { idx, .. } => format!("%{idx}"),
+                Opnd::Imm(v) if v >= 0 => format!("+0x{v:x}"),
+                Opnd::Imm(v) => format!("-0x{:x}", -(v as i128)),
+                Opnd::UImm(v) => format!("0x{v:x}"),
+                Opnd::Mem(Mem { base, disp, num_bits }) => {
+                    let base = match base {
+                        MemBase::Reg(reg_no) => format!("r{reg_no}"),
+                        MemBase::InsnOut(idx) => format!("%{idx}"),
+                    };
+                    if disp == 0 {
+                        format!("m{num_bits}[{base}]")
+                    } else {
+                        let sign = if disp > 0 { '+' } else { '-' };
+                        format!("m{num_bits}[{base}{sign}{}]", disp.abs())
+                    }
+                }
+                Opnd::Reg(Reg { reg_no, num_bits, .. }) => format!("reg{num_bits}_{reg_no}"),
+            }
+        }
+
+        fn escape_text(text: &str) -> String {
+            text.replace('\\', "\\\\").replace('\n', "\\n")
+        }
+
+        let mut out = String::new();
+
+        for (idx, insn) in self.insns.iter().enumerate() {
+            if produces_value(insn.op) {
+                out.push_str(&format!("%{idx} = "));
+            }
+
+            out.push_str(&format!("{:?}", insn.op));
+
+            if !insn.opnds.is_empty() {
+                out.push(' ');
+                let rendered: Vec<String> = insn.opnds.iter().map(|&opnd| render_opnd(opnd)).collect();
+                out.push_str(&rendered.join(", "));
+            }
+
+            if let Some(target) = insn.target {
+                out.push_str(&format!(" -> {}", render_target(target)));
+            }
+
+            if let Some(text) = &insn.text {
+                out.push_str(&format!(" ; {}", escape_text(text)));
+            }
+
+            out.push('\n');
+        }
+
+        out
+    }
+
+    /// The inverse of `to_text`: reconstruct an `Assembler` from its output.
+    /// Dispatches each line through `push_insn_parts` by op name rather than
+    /// replaying the original builder call, so this accepts any `Op`
+    /// without needing one parsing branch per builder method; `%N`
+    /// references are resolved against `self.insns[N].out`, which is why
+    /// lines must appear in the same order `to_text` wrote them (forward
+    /// references are rejected, matching `verify`'s "operand references a
+    /// prior instruction" rule). `pos_marker` can't be recovered from text
+    /// -- a parsed `PosMarker` instruction always comes back with `None` --
+    /// and a line naming a `value(..)`/`codeptr(..)`/`funptr(..)` operand or
+    /// target is rejected outright rather than guessed at.
+    pub fn parse(text: &str) -> Result<Assembler, String> {
+        fn op_from_name(name: &str) -> Option<Op> {
+            Some(match name {
+                "Comment" => Op::Comment,
+                "Label" => Op::Label,
+                "PosMarker" => Op::PosMarker,
+                "BakeString" => Op::BakeString,
+                "Add" => Op::Add,
+                "Sub" => Op::Sub,
+                "And" => Op::And,
+                "Or" => Op::Or,
+                "Xor" => Op::Xor,
+                "Not" => Op::Not,
+                "RShift" => Op::RShift,
+                "URShift" => Op::URShift,
+                "LShift" => Op::LShift,
+                "Mul" => Op::Mul,
+                "SMul" => Op::SMul,
+                "UMul" => Op::UMul,
+                "SDiv" => Op::SDiv,
+                "UDiv" => Op::UDiv,
+                "SMod" => Op::SMod,
+                "UMod" => Op::UMod,
+                "FAdd" => Op::FAdd,
+                "FSub" => Op::FSub,
+                "FMul" => Op::FMul,
+                "FDiv" => Op::FDiv,
+                "FLoad" => Op::FLoad,
+                "FStore" => Op::FStore,
+                "FCmp" => Op::FCmp,
+                "Load" => Op::Load,
+                "LoadSExt" => Op::LoadSExt,
+                "Store" => Op::Store,
+                "Lea" => Op::Lea,
+                "LeaLabel" => Op::LeaLabel,
+                "Mov" => Op::Mov,
+                "Test" => Op::Test,
+                "Cmp" => Op::Cmp,
+                "Jmp" => Op::Jmp,
+                "JmpOpnd" => Op::JmpOpnd,
+                "Jl" => Op::Jl,
+                "Jbe" => Op::Jbe,
+                "Je" => Op::Je,
+                "Jne" => Op::Jne,
+                "Jz" => Op::Jz,
+                "Jnz" => Op::Jnz,
+                "Jo" => Op::Jo,
+                "CmpJcc" => Op::CmpJcc,
+                "GuardHeap" => Op::GuardHeap,
+                "GuardImm" => Op::GuardImm,
+                "GuardFixnum" => Op::GuardFixnum,
+                "JumpTrue" => Op::JumpTrue,
+                "JumpFalse" => Op::JumpFalse,
+                "CSelZ" => Op::CSelZ,
+                "CSelNZ" => Op::CSelNZ,
+                "CSelE" => Op::CSelE,
+                "CSelNE" => Op::CSelNE,
+                "CSelL" => Op::CSelL,
+                "CSelLE" => Op::CSelLE,
+                "CSelG" => Op::CSelG,
+                "CSelGE" => Op::CSelGE,
+                "CPush" => Op::CPush,
+                "CPop" => Op::CPop,
+                "CPopInto" => Op::CPopInto,
+                "CPushAll" => Op::CPushAll,
+                "CPopAll" => Op::CPopAll,
+                "CCall" => Op::CCall,
+                "CRet" => Op::CRet,
+                "IncrCounter" => Op::IncrCounter,
+                "AtomicAdd" => Op::AtomicAdd,
+                "CmpXchg" => Op::CmpXchg,
+                "AtomicSub" => Op::AtomicSub,
+                "AtomicXchg" => Op::AtomicXchg,
+                "AtomicCmpXchg" => Op::AtomicCmpXchg,
+                "Breakpoint" => Op::Breakpoint,
+                "FrameSetup" => Op::FrameSetup,
+                "FrameTeardown" => Op::FrameTeardown,
+                "LiveReg" => Op::LiveReg,
+                _ => return None,
+            })
+        }
+
+        fn unescape_text(text: &str) -> String {
+            let mut out = String::with_capacity(text.len());
+            let mut chars = text.chars();
+
+            while let Some(c) = chars.next() {
+                if c != '\\' {
+                    out.push(c);
+                    continue;
+                }
+
+                match chars.next() {
+                    Some('n') => out.push('\n'),
+                    Some('\\') => out.push('\\'),
+                    Some(other) => { out.push('\\'); out.push(other); }
+                    None => out.push('\\'),
+                }
+            }
+
+            out
+        }
+
+        fn parse_target(tok: &str, asm: &mut Assembler) -> Result<Target, String> {
+            if let Some(idx_str) = tok.strip_prefix("label_") {
+                let idx: usize = idx_str.parse().map_err(|_| format!("bad label target {tok:?}"))?;
+
+                while asm.label_names.len() <= idx {
+                    let next = asm.label_names.len();
+                    asm.label_names.push(format!("label_{next}"));
+                }
+
+                return Ok(Target::Label(idx));
+            }
+
+            Err(format!("target {tok:?} can't be parsed back from text (only label_N targets round-trip)"))
+        }
+
+        fn parse_mem(rest: &str, asm: &Assembler) -> Result<Opnd, String> {
+            let open = rest.find('[').ok_or_else(|| format!("bad mem operand m{rest}"))?;
+            let num_bits: u8 = rest[..open].parse().map_err(|_| format!("bad mem size in m{rest}"))?;
+            let inner = rest[open + 1..].strip_suffix(']').ok_or_else(|| format!("bad mem operand m{rest}"))?;
+
+            let (base_str, disp) = match inner.rfind(['+', '-']) {
+                Some(pos) if pos > 0 => {
+                    let (base, disp_str) = inner.split_at(pos);
+                    let disp: i32 = disp_str.parse().map_err(|_| format!("bad mem displacement in m{rest}"))?;
+                    (base, disp)
+                }
+                _ => (inner, 0),
+            };
+
+            // `Opnd::mem` only accepts a 64-bit register or `InsnOut` as its
+            // base, matching the `base_reg.num_bits == 64` assertion it
+            // already enforces -- a `Mem`'s own `num_bits` (parsed above)
+            // describes the width of the value at that address, not the
+            // base register holding the address.
+            let base_opnd = if let Some(reg_no_str) = base_str.strip_prefix('r') {
+                let reg_no: u8 = reg_no_str.parse().map_err(|_| format!("bad mem base in m{rest}"))?;
+                Opnd::Reg(Reg::new(64, reg_no))
+            } else if let Some(idx_str) = base_str.strip_prefix('%') {
+                let idx: usize = idx_str.parse().map_err(|_| format!("bad mem base in m{rest}"))?;
+                asm.insns.get(idx).map(|insn| insn.out)
+                    .ok_or_else(|| format!("mem base %{idx} refers to a missing or not-yet-parsed instruction"))?
+            } else {
+                return Err(format!("bad mem base in m{rest}"));
+            };
+
+            Ok(Opnd::mem(num_bits, base_opnd, disp))
+        }
+
+        fn parse_opnd(tok: &str, asm: &Assembler) -> Result<Opnd, String> {
+            if tok == "none" {
+                return Ok(Opnd::None);
+            }
+
+            if let Some(idx_str) = tok.strip_prefix('%') {
+                let idx: usize = idx_str.parse().map_err(|_| format!("bad operand reference {tok:?}"))?;
+                return asm.insns.get(idx).map(|insn| insn.out)
+                    .ok_or_else(|| format!("{tok:?} refers to a missing or not-yet-parsed instruction"));
+            }
+
+            if let Some(hex) = tok.strip_prefix("+0x") {
+                let v = i64::from_str_radix(hex, 16).map_err(|_| format!("bad immediate {tok:?}"))?;
+                return Ok(Opnd::Imm(v));
+            }
+
+            if let Some(hex) = tok.strip_prefix("-0x") {
+                let v = i64::from_str_radix(hex, 16).map_err(|_| format!("bad immediate {tok:?}"))?;
+                return Ok(Opnd::Imm(-v));
+            }
+
+            if let Some(hex) = tok.strip_prefix("0x") {
+                let v = u64::from_str_radix(hex, 16).map_err(|_| format!("bad immediate {tok:?}"))?;
+                return Ok(Opnd::UImm(v));
+            }
+
+            if let Some(rest) = tok.strip_prefix("reg") {
+                let (bits_str, no_str) = rest.split_once('_').ok_or_else(|| format!("bad register operand {tok:?}"))?;
+                let num_bits: u8 = bits_str.parse().map_err(|_| format!("bad register operand {tok:?}"))?;
+                let reg_no: u8 = no_str.parse().map_err(|_| format!("bad register operand {tok:?}"))?;
+                return Ok(Opnd::Reg(Reg::new(num_bits, reg_no)));
+            }
+
+            if let Some(rest) = tok.strip_prefix('m') {
+                return parse_mem(rest, asm);
+            }
+
+            Err(format!("unrecognized operand {tok:?} (value(..) operands can't be reconstructed from text)"))
+        }
+
+        let mut asm = Assembler::new();
+
+        for (line_no, raw_line) in text.lines().enumerate() {
+            let line = raw_line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let err_ctx = |msg: String| format!("line {}: {msg}", line_no + 1);
+
+            let mut rest = line;
+
+            if let Some(after_percent) = rest.strip_prefix('%') {
+                if let Some(eq_pos) = after_percent.find(" = ") {
+                    let idx_str = &after_percent[..eq_pos];
+                    if !idx_str.is_empty() && idx_str.bytes().all(|b| b.is_ascii_digit()) {
+                        let idx: usize = idx_str.parse().map_err(|_| err_ctx(format!("bad instruction index {idx_str:?}")))?;
+                        if idx != asm.insns.len() {
+                            return Err(err_ctx(format!("instruction index %{idx} out of order, expected %{}", asm.insns.len())));
+                        }
+                        rest = &after_percent[eq_pos + 3..];
+                    }
+                }
+            }
+
+            let (rest, text_field) = match rest.find(" ; ") {
+                Some(i) => (&rest[..i], Some(unescape_text(&rest[i + 3..]))),
+                None => (rest, None),
+            };
+
+            let (rest, target_field) = match rest.find(" -> ") {
+                Some(i) => {
+                    let target = parse_target(&rest[i + 4..], &mut asm).map_err(&err_ctx)?;
+                    (&rest[..i], Some(target))
+                }
+                None => (rest, None),
+            };
+
+            let mut split = rest.splitn(2, ' ');
+            let mnemonic = split.next().unwrap_or("");
+            let operand_str = split.next().unwrap_or("");
+
+            let op = op_from_name(mnemonic).ok_or_else(|| err_ctx(format!("unknown mnemonic {mnemonic:?}")))?;
+
+            let opnds = if operand_str.is_empty() {
+                Vec::new()
+            } else {
+                operand_str.split(", ")
+                    .map(|tok| parse_opnd(tok, &asm))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(&err_ctx)?
+            };
+
+            asm.push_insn_parts(op, opnds, target_field, text_field, None);
+        }
+
+        Ok(asm)
+    }
+
     /// Compile with a limited number of registers
-    pub fn compile_with_num_regs(self, cb: &mut CodeBlock, num_regs: usize) -> Vec<u32>
+    pub fn compile_with_num_regs(self, cb: &mut CodeBlock, num_regs: usize) -> (CodePtr, Vec<u32>)
     {
         let mut alloc_regs = Self::get_alloc_regs();
         let alloc_regs = alloc_regs.drain(0..num_regs).collect();
         self.compile_with_regs(cb, alloc_regs)
     }
 
+    /// Optimize the instruction list ahead of splitting and register
+    /// allocation, in two stages. First `fold_constants_and_movs` drops
+    /// `Add`/`Sub`/`Or`/`Xor`/`LShift` of zero and `And` with either a
+    /// width-matching all-ones mask or a zero mask (all no-ops or constant
+    /// zero), drops a `Mov` whose source already equals its destination,
+    /// folds `Add`/`Sub`/`And` between two immediates into a single
+    /// immediate, and fuses a `Cmp` immediately followed by one of the
+    /// conditional jumps into a `CmpJcc`. Then `fold_redundant_insns` looks
+    /// at the result of that with an `AssemblerLookbackIterator` to catch
+    /// patterns that span more than one instruction at a time: a `Load`
+    /// immediately undone by a `Store` back to the same memory operand, and
+    /// a `Cmp`/`Test` that repeats the operands of an earlier one across
+    /// the conditional jump/`CSel*`/fused `CmpJcc` that already consumed
+    /// its flags.
+    /// Structured as a `Vec<Insn> -> Vec<Insn>` transform, like `split_insns`
+    /// below, so the two compose in the `compile` pipeline.
+    pub(super) fn peephole(self) -> Assembler
+    {
+        self.fold_constants_and_movs().fold_redundant_insns()
+    }
+
+    /// The single-instruction half of `peephole`; see its doc comment.
+    fn fold_constants_and_movs(mut self) -> Assembler
+    {
+        let mut asm = Assembler::new_with_label_names(take(&mut self.label_names));
+
+        // Old instruction index -> operand to use in place of its output,
+        // for instructions this pass eliminates outright (no-ops and
+        // constant folds) instead of re-emitting.
+        let mut substitutions: HashMap<usize, Opnd> = HashMap::new();
+
+        fn is_zero(opnd: Opnd) -> bool {
+            matches!(opnd, Opnd::Imm(0) | Opnd::UImm(0))
+        }
+
+        // Whether `mask` sets every bit that `base`'s width can hold, i.e.
+        // `And(base, mask)` always returns `base` unchanged.
+        fn is_all_ones(base: Opnd, mask: Opnd) -> bool {
+            let bits = match base {
+                Opnd::Reg(Reg { num_bits, .. }) |
+                Opnd::Mem(Mem { num_bits, .. }) |
+                Opnd::InsnOut { num_bits, .. } => num_bits,
+                _ => return false,
+            };
+            let all_ones: u64 = if bits >= 64 { u64::MAX } else { (1_u64 << bits) - 1 };
+
+            match mask {
+                Opnd::UImm(value) => value == all_ones,
+                Opnd::Imm(value) => (value as u64) & all_ones == all_ones,
+                _ => false,
+            }
+        }
+
+        fn imm_value(opnd: Opnd) -> Option<i64> {
+            match opnd {
+                Opnd::Imm(value) => Some(value),
+                Opnd::UImm(value) => i64::try_from(value).ok(),
+                _ => None,
+            }
+        }
+
+        fn fold_imm(op: Op, left: Opnd, right: Opnd) -> Option<Opnd> {
+            let (left, right) = (imm_value(left)?, imm_value(right)?);
+            let result = match op {
+                Op::Add => left.checked_add(right)?,
+                Op::Sub => left.checked_sub(right)?,
+                Op::And => left & right,
+                _ => return None,
+            };
+            Some(Opnd::Imm(result))
+        }
+
+        fn is_fusable_jcc(op: Op) -> bool {
+            matches!(op, Op::Jl | Op::Jbe | Op::Je | Op::Jne | Op::Jz | Op::Jnz | Op::Jo)
+        }
+
+        // The condition code a fused `CmpJcc` stashes in `text`; see the
+        // comment on `Op::CmpJcc`.
+        fn jcc_cond(op: Op) -> &'static str {
+            match op {
+                Op::Jl => "l",
+                Op::Jbe => "be",
+                Op::Je => "e",
+                Op::Jne => "ne",
+                Op::Jz => "z",
+                Op::Jnz => "nz",
+                Op::Jo => "o",
+                _ => unreachable!("not a conditional jump"),
+            }
+        }
+
+        // Resolve an operand against both the substitution map (for
+        // instructions this pass eliminated) and the iterator's usual
+        // old-index -> new-index mapping (for everything else).
+        fn resolve_opnd(opnd: Opnd, iterator: &AssemblerDrainingIterator, substitutions: &HashMap<usize, Opnd>) -> Opnd {
+            match opnd {
+                Opnd::InsnOut { idx, .. } => {
+                    match substitutions.get(&idx) {
+                        Some(&sub) => sub,
+                        None => iterator.map_opnd(opnd),
+                    }
+                }
+                Opnd::Mem(Mem { base: MemBase::InsnOut(idx), disp, num_bits }) => {
+                    match substitutions.get(&idx) {
+                        Some(&Opnd::Reg(reg)) => Opnd::Mem(Mem { base: MemBase::Reg(reg.reg_no), disp, num_bits }),
+                        Some(&other) => other,
+                        None => iterator.map_opnd(opnd),
+                    }
+                }
+                _ => iterator.map_opnd(opnd),
+            }
+        }
+
+        let mut iterator = self.into_draining_iter();
+
+        while let Some((index, mut insn)) = iterator.next_unmapped() {
+            for opnd in &mut insn.opnds {
+                *opnd = resolve_opnd(*opnd, &iterator, &substitutions);
+            }
+
+            let substituted = if insn.opnds.len() == 2 {
+                match insn.op {
+                    Op::Add | Op::Sub | Op::Or | Op::Xor | Op::LShift if is_zero(insn.opnds[1]) => Some(insn.opnds[0]),
+                    Op::And if is_all_ones(insn.opnds[0], insn.opnds[1]) => Some(insn.opnds[0]),
+                    Op::And if is_zero(insn.opnds[1]) => Some(Opnd::UImm(0)),
+                    Op::Add | Op::Sub | Op::And => fold_imm(insn.op, insn.opnds[0], insn.opnds[1]),
+                    _ => None,
+                }
+            } else {
+                None
+            };
+
+            // `Mov` has no `out` of its own -- it writes straight into its
+            // `dest` operand -- so a `Mov` whose source already equals its
+            // destination has no effect and can just be dropped, with
+            // nothing to substitute in its place.
+            let redundant_mov = insn.op == Op::Mov && insn.opnds[0] == insn.opnds[1];
+
+            if let Some(value) = substituted {
+                substitutions.insert(index, value);
+            } else if redundant_mov {
+                // Dropped with no substitution; see the comment above.
+            } else if is_fusable_jcc(insn.op) && matches!(asm.insns.last(), Some(Insn { op: Op::Cmp, .. })) {
+                // The `Cmp` is the last thing pushed and nothing ran between
+                // it and this jump, so it's safe to fuse: pop it back off
+                // and replace it with the fused form in the same slot.
+                let cmp = asm.insns.pop().unwrap();
+                asm.live_ranges.pop();
+                asm.push_insn_parts(Op::CmpJcc, cmp.opnds, insn.target, Some(jcc_cond(insn.op).to_string()), None);
+            } else {
+                asm.push_insn_parts(insn.op, insn.opnds, insn.target, insn.text, insn.pos_marker);
+            }
+
+            iterator.map_insn_index(&mut asm);
+        }
+
+        asm
+    }
+
+    /// The cross-instruction half of `peephole`; see its doc comment. Unlike
+    /// `fold_constants_and_movs`, the patterns here need to see more than
+    /// the instruction currently being visited, so this walks the list with
+    /// an `AssemblerLookbackIterator` first to decide what to drop, then
+    /// rebuilds it through the usual `AssemblerDrainingIterator` pass.
+    fn fold_redundant_insns(self) -> Assembler
+    {
+        fn is_fusable_jcc(op: Op) -> bool {
+            matches!(op, Op::Jl | Op::Jbe | Op::Je | Op::Jne | Op::Jz | Op::Jnz | Op::Jo)
+        }
+
+        // Whether `op` reads the flags left behind by a `Cmp`/`Test`, i.e.
+        // an earlier comparison with identical operands is still safe to
+        // drop as long as nothing other than one of these sits between them.
+        fn reads_flags(op: Op) -> bool {
+            is_fusable_jcc(op) || matches!(
+                op,
+                Op::CSelZ | Op::CSelNZ | Op::CSelE | Op::CSelNE |
+                Op::CSelL | Op::CSelLE | Op::CSelG | Op::CSelGE
+            )
+        }
+
+        // Each `InsnOut` index's use count, keyed the same way
+        // `push_insn`'s `live_ranges` bookkeeping walks operands, so the
+        // `Load`/`Store` fold below only fires when nothing else still
+        // needs the value the `Load` produced.
+        let mut use_counts: HashMap<usize, usize> = HashMap::new();
+        for insn in &self.insns {
+            for opnd in &insn.opnds {
+                match opnd {
+                    Opnd::InsnOut { idx, .. } => *use_counts.entry(*idx).or_insert(0) += 1,
+                    Opnd::Mem(Mem { base: MemBase::InsnOut(idx), .. }) => *use_counts.entry(*idx).or_insert(0) += 1,
+                    _ => {}
+                }
+            }
+        }
+
+        let lookback = self.into_lookback_iter();
+
+        // Indices of instructions to drop outright when rebuilding below.
+        // Neither pattern this pass looks for ever produces a value another
+        // surviving instruction still reads -- a dropped `Store`/`Cmp`/
+        // `Test` has no `out` of its own, and a dropped `Load`'s `out` is,
+        // by the `use_counts` check, only read by the `Store` it's dropped
+        // alongside -- so there's no substitution map to maintain the way
+        // `fold_constants_and_movs` needs one.
+        let mut drop: HashSet<usize> = HashSet::new();
+
+        while let Some((index, insn)) = lookback.next_unmapped() {
+            match insn.op {
+                Op::Store if insn.opnds.len() == 2 => {
+                    let dest = insn.opnds[0];
+
+                    if let Opnd::InsnOut { idx: load_idx, .. } = insn.opnds[1] {
+                        // `next_unmapped` has already moved past `insn`, so
+                        // `get_previous()` would be `insn` itself.
+                        if let Some(Insn { op: Op::Load, opnds: load_opnds, .. }) = lookback.get_relative(-2) {
+                            if load_idx == index - 1
+                                && dest == load_opnds[0]
+                                && use_counts.get(&load_idx).copied().unwrap_or(0) == 1
+                            {
+                                drop.insert(index);
+                                drop.insert(load_idx);
+                            }
+                        }
+                    }
+                }
+                Op::Cmp | Op::Test => {
+                    if let (Some(Insn { op: jcc_op, .. }), Some(Insn { op: prev_op @ (Op::Cmp | Op::Test), opnds: prev_opnds, .. })) =
+                        (lookback.get_relative(-2), lookback.get_relative(-3))
+                    {
+                        if reads_flags(*jcc_op) && *prev_op == insn.op && insn.opnds == *prev_opnds {
+                            drop.insert(index);
+                        }
+                    }
+
+                    // `fold_constants_and_movs` runs first and already fuses
+                    // a `Cmp` immediately followed by a jump into a
+                    // single `CmpJcc`, so the two-instructions-back shape
+                    // above never matches across that fusion -- the earlier
+                    // `Cmp` is gone, folded into the `CmpJcc` that replaced
+                    // it. Check one instruction back for that fused form
+                    // directly: a `CmpJcc` already reads the same flags a
+                    // bare `Jcc` would, so a `Cmp` repeating its operands
+                    // right after it is just as redundant.
+                    if let Some(Insn { op: Op::CmpJcc, opnds: prev_opnds, .. }) = lookback.get_relative(-2) {
+                        if insn.op == Op::Cmp && insn.opnds == *prev_opnds {
+                            drop.insert(index);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let AssemblerLookbackIterator { mut asm, .. } = lookback;
+        let mut result = Assembler::new_with_label_names(take(&mut asm.label_names));
+        let mut iterator = asm.into_draining_iter();
+
+        while let Some((index, insn)) = iterator.next_unmapped() {
+            if !drop.contains(&index) {
+                let opnds = insn.opnds.into_iter().map(|opnd| iterator.map_opnd(opnd)).collect();
+                result.push_insn_parts(insn.op, opnds, insn.target, insn.text, insn.pos_marker);
+            }
+
+            iterator.map_insn_index(&mut result);
+        }
+
+        result
+    }
+
+    /// Rewrite instructions whose operand combination isn't directly
+    /// encodable -- `Add`/`Sub`/`And`/`Or`/`Xor`/`Cmp`/`Test` between two
+    /// memory operands, or an arithmetic/comparison op between two
+    /// immediates that `peephole` didn't already fold away -- into a
+    /// `Load` of the left operand into a fresh register-sized temporary
+    /// followed by the constrained op. Every rewrite goes through the
+    /// normal `push_insn` path, so `live_ranges` stays consistent for the
+    /// temporaries this introduces without any extra bookkeeping, the same
+    /// way `alloc_regs`'s spill-induced `Store`s do.
+    pub(super) fn split_insns(mut self) -> Assembler
+    {
+        let mut asm = Assembler::new_with_label_names(take(&mut self.label_names));
+
+        fn unencodable(op: Op, left: Opnd, right: Opnd) -> bool {
+            match op {
+                Op::Add | Op::Sub | Op::And | Op::Or | Op::Xor | Op::Cmp | Op::Test => {
+                    matches!((left, right), (Opnd::Mem(_), Opnd::Mem(_)))
+                        || matches!((left, right), (Opnd::Imm(_) | Opnd::UImm(_), Opnd::Imm(_) | Opnd::UImm(_)))
+                }
+                _ => false,
+            }
+        }
+
+        let mut iterator = self.into_draining_iter();
+
+        while let Some((_, mut insn)) = iterator.next_mapped() {
+            if insn.opnds.len() == 2 && unencodable(insn.op, insn.opnds[0], insn.opnds[1]) {
+                insn.opnds[0] = asm.load(insn.opnds[0]);
+            }
+
+            asm.push_insn_parts(insn.op, insn.opnds, insn.target, insn.text, insn.pos_marker);
+            iterator.map_insn_index(&mut asm);
+        }
+
+        asm
+    }
+
     /// Consume the assembler by creating a new draining iterator.
     pub fn into_draining_iter(self) -> AssemblerDrainingIterator {
         AssemblerDrainingIterator::new(self)
@@ -868,6 +1980,34 @@ impl Assembler {
         self.push_insn(Insn { op: Op::And, opnds: vec![left, right], out: Opnd::None, text: None, target: None, pos_marker: None })
     }
 
+    /// Atomically add `val` into the memory at `mem` and return the value
+    /// that was there beforehand (`LOCK XADD`).
+    #[must_use]
+    pub fn atomic_add(&mut self, mem: Opnd, val: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::AtomicAdd, opnds: vec![mem, val], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
+    /// Atomic compare-and-swap that returns a 0/1 success flag rather than
+    /// the prior memory contents (see `cmpxchg` for the latter).
+    #[must_use]
+    pub fn atomic_cmpxchg(&mut self, mem: Opnd, expected: Opnd, desired: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::AtomicCmpXchg, opnds: vec![mem, expected, desired], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
+    /// Atomically subtract `val` from the memory at `mem` and return the
+    /// value that was there beforehand.
+    #[must_use]
+    pub fn atomic_sub(&mut self, mem: Opnd, val: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::AtomicSub, opnds: vec![mem, val], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
+    /// Atomically swap `val` into the memory at `mem` and return the value
+    /// that was there beforehand (`XCHG`, implicitly locked).
+    #[must_use]
+    pub fn atomic_xchg(&mut self, mem: Opnd, val: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::AtomicXchg, opnds: vec![mem, val], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
     pub fn bake_string(&mut self, text: &str) {
         self.push_insn(Insn { op: Op::BakeString, opnds: vec![], out: Opnd::None, text: Some(text.to_string()), target: None, pos_marker: None });
     }
@@ -876,6 +2016,12 @@ impl Assembler {
         self.push_insn(Insn { op: Op::Breakpoint, opnds: vec![], out: Opnd::None, text: None, target: None, pos_marker: None });
     }
 
+    /// Call the C function at `fptr` with `opnds` as arguments. The first
+    /// `C_ARG_OPNDS.len()` are marshalled into argument registers and any
+    /// remainder is passed on the C stack, both handled by `x86_split`'s
+    /// and `arm64_split`'s `CCall` arms; pass `Opnd::None` for a register
+    /// slot whose value is already sitting in the right place to skip
+    /// marshalling it (see `reorder_c_args`).
     #[must_use]
     pub fn ccall(&mut self, fptr: *const u8, opnds: Vec<Opnd>) -> Opnd {
         self.push_insn(Insn { op: Op::CCall, opnds, out: Opnd::None, text: None, target: Some(Target::FunPtr(fptr)), pos_marker: None })
@@ -885,6 +2031,15 @@ impl Assembler {
         self.push_insn(Insn { op: Op::Cmp, opnds: vec![left, right], out: Opnd::None, text: None, target: None, pos_marker: None });
     }
 
+    /// Atomic compare-and-swap (`LOCK CMPXCHG`): if the memory at `mem`
+    /// holds `expected`, stores `desired` there. Either way, returns the
+    /// value that was actually in memory, which the instruction leaves in
+    /// RAX and `x86_emit` copies into the returned operand.
+    #[must_use]
+    pub fn cmpxchg(&mut self, mem: Opnd, expected: Opnd, desired: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::CmpXchg, opnds: vec![mem, expected, desired], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
     pub fn comment(&mut self, text: &str) {
         self.push_insn(Insn { op: Op::Comment, opnds: vec![], out: Opnd::None, text: Some(text.to_string()), target: None, pos_marker: None });
     }
@@ -953,6 +2108,38 @@ impl Assembler {
         self.push_insn(Insn { op: Op::CSelZ, opnds: vec![truthy, falsy], out: Opnd::None, text: None, target: None, pos_marker: None })
     }
 
+    /// Signed divide, defaulting to the common case callers reach for `div`
+    /// to get; same as `sdiv`/`Op::SDiv`. The unsigned counterpart is `udiv`.
+    #[must_use]
+    pub fn div(&mut self, left: Opnd, right: Opnd) -> Opnd {
+        self.sdiv(left, right)
+    }
+
+    #[must_use]
+    pub fn fadd(&mut self, left: Opnd, right: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::FAdd, opnds: vec![left, right], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
+    /// Compare two doubles; see `Op::FCmp`.
+    pub fn fcmp(&mut self, left: Opnd, right: Opnd) {
+        self.push_insn(Insn { op: Op::FCmp, opnds: vec![left, right], out: Opnd::None, text: None, target: None, pos_marker: None });
+    }
+
+    #[must_use]
+    pub fn fdiv(&mut self, left: Opnd, right: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::FDiv, opnds: vec![left, right], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
+    #[must_use]
+    pub fn fload(&mut self, opnd: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::FLoad, opnds: vec![opnd], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
+    #[must_use]
+    pub fn fmul(&mut self, left: Opnd, right: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::FMul, opnds: vec![left, right], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
     pub fn frame_setup(&mut self) {
         self.push_insn(Insn { op: Op::FrameSetup, opnds: vec![], out: Opnd::None, text: None, target: None, pos_marker: None });
     }
@@ -961,6 +2148,27 @@ impl Assembler {
         self.push_insn(Insn { op: Op::FrameTeardown, opnds: vec![], out: Opnd::None, text: None, target: None, pos_marker: None });
     }
 
+    pub fn fstore(&mut self, dest: Opnd, src: Opnd) {
+        self.push_insn(Insn { op: Op::FStore, opnds: vec![dest, src], out: Opnd::None, text: None, target: None, pos_marker: None });
+    }
+
+    #[must_use]
+    pub fn fsub(&mut self, left: Opnd, right: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::FSub, opnds: vec![left, right], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
+    pub fn guard_fixnum(&mut self, opnd: Opnd, side_exit: Target) {
+        self.push_insn(Insn { op: Op::GuardFixnum, opnds: vec![opnd], out: Opnd::None, text: None, target: Some(side_exit), pos_marker: None });
+    }
+
+    pub fn guard_heap(&mut self, opnd: Opnd, side_exit: Target) {
+        self.push_insn(Insn { op: Op::GuardHeap, opnds: vec![opnd], out: Opnd::None, text: None, target: Some(side_exit), pos_marker: None });
+    }
+
+    pub fn guard_imm(&mut self, opnd: Opnd, side_exit: Target) {
+        self.push_insn(Insn { op: Op::GuardImm, opnds: vec![opnd], out: Opnd::None, text: None, target: Some(side_exit), pos_marker: None });
+    }
+
     pub fn incr_counter(&mut self, mem: Opnd, value: Opnd) {
         self.push_insn(Insn { op: Op::IncrCounter, opnds: vec![mem, value], out: Opnd::None, text: None, target: None, pos_marker: None });
     }
@@ -997,6 +2205,14 @@ impl Assembler {
         self.push_insn(Insn { op: Op::Jo, opnds: vec![], out: Opnd::None, text: None, target: Some(target), pos_marker: None });
     }
 
+    pub fn jump_false(&mut self, opnd: Opnd, target: Target) {
+        self.push_insn(Insn { op: Op::JumpFalse, opnds: vec![opnd], out: Opnd::None, text: None, target: Some(target), pos_marker: None });
+    }
+
+    pub fn jump_true(&mut self, opnd: Opnd, target: Target) {
+        self.push_insn(Insn { op: Op::JumpTrue, opnds: vec![opnd], out: Opnd::None, text: None, target: Some(target), pos_marker: None });
+    }
+
     pub fn jz(&mut self, target: Target) {
         self.push_insn(Insn { op: Op::Jz, opnds: vec![], out: Opnd::None, text: None, target: Some(target), pos_marker: None });
     }
@@ -1021,6 +2237,64 @@ impl Assembler {
         self.push_insn(Insn { op: Op::Load, opnds: vec![opnd], out: Opnd::None, text: None, target: None, pos_marker: None })
     }
 
+    /// Move `opnd` into a fixed destination operand, typically an ABI
+    /// register, rather than letting the register allocator pick an output.
+    /// Used to marshal values into place ahead of fixed-register
+    /// instructions like `CCall`. A no-op if `opnd` is already `dest`.
+    pub fn load_into(&mut self, dest: Opnd, opnd: Opnd) {
+        if dest != opnd {
+            self.mov(dest, opnd);
+        }
+    }
+
+    /// Move each of `opnds` into the corresponding `C_ARG_OPNDS` register
+    /// ahead of a `CCall`. Naively moving them in order can clobber an
+    /// argument register that a later operand still needs to read from
+    /// (e.g. two arguments swapping registers), so moves whose destination
+    /// nothing else depends on are performed first, and any remaining
+    /// cycle is broken by stashing one of its registers in a scratch
+    /// temporary before unwinding the rest of the cycle. Shared by every
+    /// platform's instruction-splitting pass since `C_ARG_OPNDS` is the
+    /// only thing that differs between them here.
+    ///
+    /// `Opnd::None` marks a slot as already in place: the caller is
+    /// asserting the value already sits in its `C_ARG_OPNDS` register (for
+    /// example, one forwarded unmodified from this frame's own incoming
+    /// arguments), so there's nothing to move and the slot is skipped
+    /// outright rather than going through the usual `dest != src` check
+    /// (which can't be evaluated yet for a value still behind an
+    /// unresolved `Opnd::InsnOut`).
+    pub(super) fn reorder_c_args(&mut self, opnds: &[Opnd]) {
+        let mut pending: Vec<(Opnd, Opnd)> = opnds.iter()
+            .enumerate()
+            .map(|(idx, &opnd)| (C_ARG_OPNDS[idx], opnd))
+            .filter(|&(dest, src)| dest != src && src != Opnd::None)
+            .collect();
+
+        while !pending.is_empty() {
+            // A move is safe to perform now if no other pending move still
+            // needs to read the current value out of its destination.
+            let ready_idx = pending.iter().position(|&(dest, _)| {
+                !pending.iter().any(|&(_, src)| src == dest)
+            });
+
+            if let Some(ready_idx) = ready_idx {
+                let (dest, src) = pending.remove(ready_idx);
+                self.load_into(dest, src);
+            } else {
+                let (dest, src) = pending.remove(0);
+                let scratch = self.load(dest);
+                self.load_into(dest, src);
+
+                for (_, other_src) in pending.iter_mut() {
+                    if *other_src == dest {
+                        *other_src = scratch;
+                    }
+                }
+            }
+        }
+    }
+
     #[must_use]
     pub fn load_sext(&mut self, opnd: Opnd) -> Opnd {
         self.push_insn(Insn { op: Op::LoadSExt, opnds: vec![opnd], out: Opnd::None, text: None, target: None, pos_marker: None })
@@ -1031,10 +2305,24 @@ impl Assembler {
         self.push_insn(Insn { op: Op::LShift, opnds: vec![opnd, shift], out: Opnd::None, text: None, target: None, pos_marker: None })
     }
 
+    /// Signed remainder, defaulting to the common case callers reach for
+    /// `modulo` to get; same as `smod`/`Op::SMod`. The unsigned counterpart
+    /// is `umod`.
+    #[must_use]
+    pub fn modulo(&mut self, left: Opnd, right: Opnd) -> Opnd {
+        self.smod(left, right)
+    }
+
     pub fn mov(&mut self, dest: Opnd, src: Opnd) {
         self.push_insn(Insn { op: Op::Mov, opnds: vec![dest, src], out: Opnd::None, text: None, target: None, pos_marker: None });
     }
 
+    /// Truncating signed multiply; see `Op::Mul`.
+    #[must_use]
+    pub fn mul(&mut self, left: Opnd, right: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::Mul, opnds: vec![left, right], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
     #[must_use]
     pub fn not(&mut self, opnd: Opnd) -> Opnd {
         self.push_insn(Insn { op: Op::Not, opnds: vec![opnd], out: Opnd::None, text: None, target: None, pos_marker: None })
@@ -1046,7 +2334,7 @@ impl Assembler {
     }
 
     //pub fn pos_marker<F: FnMut(CodePtr)>(&mut self, marker_fn: F)
-    pub fn pos_marker(&mut self, marker_fn: impl Fn(CodePtr) + 'static) {
+    pub fn pos_marker(&mut self, marker_fn: impl FnOnce(CodePtr) + 'static) {
         self.push_insn(Insn { op: Op::PosMarker, opnds: vec![], out: Opnd::None, text: None, target: None, pos_marker: Some(Box::new(marker_fn)) });
     }
 
@@ -1055,6 +2343,24 @@ impl Assembler {
         self.push_insn(Insn { op: Op::RShift, opnds: vec![opnd, shift], out: Opnd::None, text: None, target: None, pos_marker: None })
     }
 
+    /// Signed divide; see `Op::SDiv`. Returns the quotient.
+    #[must_use]
+    pub fn sdiv(&mut self, left: Opnd, right: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::SDiv, opnds: vec![left, right], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
+    /// Signed remainder; see `Op::SMod`. Returns the remainder.
+    #[must_use]
+    pub fn smod(&mut self, left: Opnd, right: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::SMod, opnds: vec![left, right], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
+    /// Full-width signed multiply; see `Op::SMul`.
+    #[must_use]
+    pub fn smul(&mut self, left: Opnd, right: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::SMul, opnds: vec![left, right], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
     pub fn store(&mut self, dest: Opnd, src: Opnd) {
         self.push_insn(Insn { op: Op::Store, opnds: vec![dest, src], out: Opnd::None, text: None, target: None, pos_marker: None });
     }
@@ -1068,6 +2374,24 @@ impl Assembler {
         self.push_insn(Insn { op: Op::Test, opnds: vec![left, right], out: Opnd::None, text: None, target: None, pos_marker: None });
     }
 
+    /// Unsigned divide; see `Op::UDiv`. Returns the quotient.
+    #[must_use]
+    pub fn udiv(&mut self, left: Opnd, right: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::UDiv, opnds: vec![left, right], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
+    /// Unsigned remainder; see `Op::UMod`. Returns the remainder.
+    #[must_use]
+    pub fn umod(&mut self, left: Opnd, right: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::UMod, opnds: vec![left, right], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
+    /// Full-width unsigned multiply; see `Op::UMul`.
+    #[must_use]
+    pub fn umul(&mut self, left: Opnd, right: Opnd) -> Opnd {
+        self.push_insn(Insn { op: Op::UMul, opnds: vec![left, right], out: Opnd::None, text: None, target: None, pos_marker: None })
+    }
+
     #[must_use]
     pub fn urshift(&mut self, opnd: Opnd, shift: Opnd) -> Opnd {
         self.push_insn(Insn { op: Op::URShift, opnds: vec![opnd, shift], out: Opnd::None, text: None, target: None, pos_marker: None })
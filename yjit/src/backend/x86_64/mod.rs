@@ -33,6 +33,20 @@ pub const _C_ARG_OPNDS: [Opnd; 6] = [
 pub const C_RET_REG: Reg = RAX_REG;
 pub const _C_RET_OPND: Opnd = Opnd::Reg(RAX_REG);
 
+// The native (machine) stack pointer, used for register-allocator spill
+// slots. This is distinct from `_SP`, which is the interpreter's Ruby-level
+// stack pointer (aliased to a callee-saved register) and must never be used
+// to address spill slots.
+pub const NATIVE_STACK_PTR_REG: Reg = RSP_REG;
+
+// `imul`/`mul`/`idiv`/`div` are one-operand forms that implicitly read and
+// write RAX:RDX; `alloc_regs` pins `SMul`/`UMul`/`SDiv`/`UDiv`/`SMod`/`UMod`
+// output to these names so the same pinning logic can be shared with arm64,
+// which has no such implicit-register constraint but still needs a concrete
+// register to route the builders' single `out` operand through.
+pub const MULDIV_OUT_REG: Reg = RAX_REG;
+pub const MULDIV_REM_REG: Reg = RDX_REG;
+
 // The number of bytes that are generated by jmp_ptr
 pub const JMP_PTR_BYTES: usize = 6;
 
@@ -97,6 +111,19 @@ impl Assembler
         ]
     }
 
+    /// Get the list of XMM registers from which we can allocate for
+    /// floating-point values, kept separate from `get_alloc_regs()`'s
+    /// general-purpose pool since the two register files don't compete
+    /// with each other for space.
+    pub fn get_fp_alloc_regs() -> Vec<Reg>
+    {
+        vec![
+            XMM0_REG,
+            XMM1_REG,
+            XMM2_REG,
+        ]
+    }
+
     /// Get a list of all of the caller-save registers
     pub fn get_caller_save_regs() -> Vec<Reg> {
         vec![RAX_REG, RCX_REG, RDX_REG, RSI_REG, RDI_REG, R8_REG, R9_REG, R10_REG, R11_REG]
@@ -136,7 +163,7 @@ impl Assembler
             //     VALUEs alive. This is a sort of canonicalization.
             let mut unmapped_opnds: Vec<Opnd> = vec![];
 
-            let is_load = matches!(insn, Insn::Load { .. } | Insn::LoadInto { .. });
+            let is_load = insn.op == Op::Load;
             let mut opnd_iter = insn.opnd_iter_mut();
 
             while let Some(opnd) = opnd_iter.next() {
@@ -160,162 +187,444 @@ impl Assembler
             // We are replacing instructions here so we know they are already
             // being used. It is okay not to use their output here.
             #[allow(unused_must_use)]
-            match &mut insn {
-                Insn::Add { left, right, out } |
-                Insn::Sub { left, right, out } |
-                Insn::And { left, right, out } |
-                Insn::Or { left, right, out } |
-                Insn::Xor { left, right, out } => {
+            match insn.op {
+                Op::Add => {
+                    // x86 `add` clobbers its first operand in place, so
+                    // whenever `left` is still needed afterwards we'd
+                    // otherwise `mov` it into a fresh register before
+                    // adding it (the cases below). When `right` is a small
+                    // enough displacement to encode directly and `left` is
+                    // already register-shaped, skip that `mov` and compute
+                    // the sum with a single non-destructive `lea` instead
+                    // -- it leaves `left` untouched and doesn't affect
+                    // flags. Two-register adds still fall back to mov+add:
+                    // this backend's `Mem` operand has no SIB/index
+                    // addressing to encode `lea out, [left + right]` for a
+                    // register `right`.
+                    let small_disp = match unmapped_opnds[1] {
+                        Opnd::Imm(value) => i32::try_from(value).ok(),
+                        Opnd::UImm(value) => i32::try_from(value).ok(),
+                        _ => None,
+                    };
+
+                    let left_is_reg_shaped = matches!(unmapped_opnds[0], Opnd::Reg(_) | Opnd::InsnOut { .. });
+
+                    let needs_protecting = match unmapped_opnds[0] {
+                        Opnd::Mem(_) | Opnd::Reg(_) => true,
+                        Opnd::InsnOut { idx, .. } => live_ranges[idx] > index,
+                        _ => false,
+                    };
+
+                    if needs_protecting && left_is_reg_shaped {
+                        if let Some(disp) = small_disp {
+                            insn.out = asm.lea(Opnd::mem(insn.opnds[0].rm_num_bits(), insn.opnds[0], disp));
+                            iterator.map_insn_index(&mut asm);
+                            continue;
+                        }
+                    }
+
+                    // x86 permits one memory operand per ALU instruction, so
+                    // unlike the old all-or-nothing load, only force a load
+                    // when both operands are memory (mem-mem isn't
+                    // encodable; fold `left` in place and load `right`
+                    // instead) or when `left` is something other than a
+                    // plain memory operand whose in-place mutation is
+                    // exactly the intended write (a pinned register like
+                    // the stack pointer, or an SSA value still live past
+                    // this instruction).
                     match (unmapped_opnds[0], unmapped_opnds[1]) {
                         (Opnd::Mem(_), Opnd::Mem(_)) => {
-                            *left = asm.load(*left);
-                            *right = asm.load(*right);
+                            insn.opnds[1] = asm.load(insn.opnds[1]);
+                        },
+                        // Instruction output whose live range spans beyond this instruction
+                        (Opnd::InsnOut { idx, .. }, _) => {
+                            if live_ranges[idx] > index {
+                                insn.opnds[0] = asm.load(insn.opnds[0]);
+                            }
                         },
-                        (Opnd::Mem(_), Opnd::UImm(_) | Opnd::Imm(_)) => {
-                            *left = asm.load(*left);
+                        (Opnd::Reg(_), _) => {
+                            insn.opnds[0] = asm.load(insn.opnds[0]);
                         },
+                        _ => {}
+                    };
+
+                    insn.out = if matches!(insn.opnds[0], Opnd::Mem(_)) {
+                        insn.opnds[0]
+                    } else {
+                        asm.next_opnd_out(Opnd::match_num_bits(&[insn.opnds[0], insn.opnds[1]]))
+                    };
+                    asm.push_insn(insn);
+                },
+                Op::Mul => {
+                    // Truncating two-operand `imul` doesn't touch RDX, but
+                    // unlike `Add`/`Sub`/`And`/`Or`/`Xor` it has no
+                    // memory-destination encoding at all -- `imul r, r/m`
+                    // always writes back to a register -- so `left` always
+                    // has to be loaded into one when it's a `Mem` operand,
+                    // regardless of what `right` is (a `Mem` `right` is
+                    // still fine as `imul`'s r/m source).
+                    match (unmapped_opnds[0], unmapped_opnds[1]) {
                         // Instruction output whose live range spans beyond this instruction
                         (Opnd::InsnOut { idx, .. }, _) => {
                             if live_ranges[idx] > index {
-                                *left = asm.load(*left);
+                                insn.opnds[0] = asm.load(insn.opnds[0]);
                             }
                         },
-                        // We have to load memory operands to avoid corrupting them
-                        (Opnd::Mem(_) | Opnd::Reg(_), _) => {
-                            *left = asm.load(*left);
+                        (Opnd::Reg(_), _) => {
+                            insn.opnds[0] = asm.load(insn.opnds[0]);
+                        },
+                        (Opnd::Mem(_), _) => {
+                            insn.opnds[0] = asm.load(insn.opnds[0]);
                         },
                         _ => {}
                     };
 
-                    *out = asm.next_opnd_out(Opnd::match_num_bits(&[*left, *right]));
+                    insn.out = asm.next_opnd_out(Opnd::match_num_bits(&[insn.opnds[0], insn.opnds[1]]));
+                    asm.push_insn(insn);
+                },
+                Op::SMul | Op::UMul => {
+                    // The one-operand `imul`/`mul` forms implicitly multiply
+                    // RAX by `right`, so pin `left` there up front the same
+                    // way `CmpXchg` pins its comparand, and leave the
+                    // product in RAX.
+                    asm.load_into(Opnd::Reg(RAX_REG), insn.opnds[0]);
+                    insn.opnds[0] = Opnd::Reg(RAX_REG);
+                    insn.out = Opnd::Reg(RAX_REG);
+                    asm.push_insn(insn);
+                },
+                Op::SDiv => {
+                    // IDIV divides the 128-bit value in RDX:RAX by `right`,
+                    // so pin `left` into RAX and sign-extend it into RDX
+                    // (the same thing `CQO` does) ahead of the instruction,
+                    // by reusing the existing arithmetic-shift opcode rather
+                    // than inventing a new one. The quotient lands in RAX.
+                    asm.load_into(Opnd::Reg(RAX_REG), insn.opnds[0]);
+                    asm.load_into(Opnd::Reg(RDX_REG), Opnd::Reg(RAX_REG));
+                    asm.rshift(Opnd::Reg(RDX_REG), Opnd::UImm(63));
+                    insn.opnds[0] = Opnd::Reg(RAX_REG);
+                    insn.out = Opnd::Reg(RAX_REG);
+                    asm.push_insn(insn);
+                },
+                Op::SMod => {
+                    // Same split as `SDiv`, but the remainder in RDX is the
+                    // result of interest instead of the quotient in RAX.
+                    asm.load_into(Opnd::Reg(RAX_REG), insn.opnds[0]);
+                    asm.load_into(Opnd::Reg(RDX_REG), Opnd::Reg(RAX_REG));
+                    asm.rshift(Opnd::Reg(RDX_REG), Opnd::UImm(63));
+                    insn.opnds[0] = Opnd::Reg(RAX_REG);
+                    insn.out = Opnd::Reg(RDX_REG);
                     asm.push_insn(insn);
                 },
-                Insn::Cmp { left, right } |
-                Insn::Test { left, right } => {
-                    if let (Opnd::Mem(_), Opnd::Mem(_)) = (&left, &right) {
-                        let loaded = asm.load(*right);
-                        *right = loaded;
+                Op::UDiv => {
+                    // Same as the SDiv arm above, but DIV treats RDX:RAX as
+                    // unsigned, so RDX is simply zeroed instead of sign-
+                    // extended.
+                    asm.load_into(Opnd::Reg(RAX_REG), insn.opnds[0]);
+                    asm.load_into(Opnd::Reg(RDX_REG), Opnd::UImm(0));
+                    insn.opnds[0] = Opnd::Reg(RAX_REG);
+                    insn.out = Opnd::Reg(RAX_REG);
+                    asm.push_insn(insn);
+                },
+                Op::UMod => {
+                    // Same split as `UDiv`, but the remainder in RDX is the
+                    // result of interest instead of the quotient in RAX.
+                    asm.load_into(Opnd::Reg(RAX_REG), insn.opnds[0]);
+                    asm.load_into(Opnd::Reg(RDX_REG), Opnd::UImm(0));
+                    insn.opnds[0] = Opnd::Reg(RAX_REG);
+                    insn.out = Opnd::Reg(RDX_REG);
+                    asm.push_insn(insn);
+                },
+                Op::Sub | Op::And | Op::Or | Op::Xor => {
+                    // See the `Op::Add` arm above: fold a lone memory
+                    // operand into the instruction directly instead of
+                    // loading it first.
+                    match (unmapped_opnds[0], unmapped_opnds[1]) {
+                        (Opnd::Mem(_), Opnd::Mem(_)) => {
+                            insn.opnds[1] = asm.load(insn.opnds[1]);
+                        },
+                        // Instruction output whose live range spans beyond this instruction
+                        (Opnd::InsnOut { idx, .. }, _) => {
+                            if live_ranges[idx] > index {
+                                insn.opnds[0] = asm.load(insn.opnds[0]);
+                            }
+                        },
+                        (Opnd::Reg(_), _) => {
+                            insn.opnds[0] = asm.load(insn.opnds[0]);
+                        },
+                        _ => {}
+                    };
+
+                    insn.out = if matches!(insn.opnds[0], Opnd::Mem(_)) {
+                        insn.opnds[0]
+                    } else {
+                        asm.next_opnd_out(Opnd::match_num_bits(&[insn.opnds[0], insn.opnds[1]]))
+                    };
+                    asm.push_insn(insn);
+                },
+                Op::FAdd | Op::FSub | Op::FMul | Op::FDiv => {
+                    // Unlike the integer ALU ops above, scalar SSE2
+                    // `addsd`/`subsd`/`mulsd`/`divsd` require their
+                    // destination to be an XMM *register* -- memory is only
+                    // legal as the second/source operand -- so `left` always
+                    // has to be loaded into one when it's a `Mem` operand,
+                    // regardless of what `right` is, through `fload`/`movsd`
+                    // since XMM registers can't be loaded with a plain `mov`.
+                    match (unmapped_opnds[0], unmapped_opnds[1]) {
+                        // Instruction output whose live range spans beyond this instruction
+                        (Opnd::InsnOut { idx, .. }, _) => {
+                            if live_ranges[idx] > index {
+                                insn.opnds[0] = asm.fload(insn.opnds[0]);
+                            }
+                        },
+                        (Opnd::Reg(_), _) => {
+                            insn.opnds[0] = asm.fload(insn.opnds[0]);
+                        },
+                        (Opnd::Mem(_), _) => {
+                            insn.opnds[0] = asm.fload(insn.opnds[0]);
+                        },
+                        _ => {}
+                    };
+
+                    insn.out = asm.next_opnd_out(Opnd::match_num_bits(&[insn.opnds[0], insn.opnds[1]]));
+                    asm.push_insn(insn);
+                },
+                Op::Cmp | Op::Test | Op::CmpJcc => {
+                    if let (Opnd::Mem(_), Opnd::Mem(_)) = (insn.opnds[0], insn.opnds[1]) {
+                        insn.opnds[1] = asm.load(insn.opnds[1]);
+                    }
+
+                    if insn.op == Op::CmpJcc {
+                        // Expand the fused form back into `Cmp` + `Jcc`.
+                        asm.cmp(insn.opnds[0], insn.opnds[1]);
+                        let jcc_op = match insn.text.as_deref() {
+                            Some("l") => Op::Jl, Some("be") => Op::Jbe, Some("e") => Op::Je, Some("ne") => Op::Jne,
+                            Some("z") => Op::Jz, Some("nz") => Op::Jnz, Some("o") => Op::Jo,
+                            other => unreachable!("unknown CmpJcc condition code {other:?}"),
+                        };
+                        asm.push_insn_parts(jcc_op, vec![], insn.target, None, None);
+                    } else {
+                        asm.push_insn(insn);
+                    }
+                },
+                // `ucomisd` has the same two-memory-operand restriction as the
+                // integer `cmp` above, but the replacement load has to go
+                // through `fload` since this is an XMM comparison.
+                Op::FCmp => {
+                    if let (Opnd::Mem(_), Opnd::Mem(_)) = (insn.opnds[0], insn.opnds[1]) {
+                        insn.opnds[1] = asm.fload(insn.opnds[1]);
                     }
 
                     asm.push_insn(insn);
                 },
                 // These instructions modify their input operand in-place, so we
                 // may need to load the input value to preserve it
-                Insn::LShift { opnd, shift, out } |
-                Insn::RShift { opnd, shift, out } |
-                Insn::URShift { opnd, shift, out } => {
+                Op::LShift | Op::RShift | Op::URShift => {
                     match (&unmapped_opnds[0], &unmapped_opnds[1]) {
                         // Instruction output whose live range spans beyond this instruction
                         (Opnd::InsnOut { idx, .. }, _) => {
                             if live_ranges[*idx] > index {
-                                *opnd = asm.load(*opnd);
+                                insn.opnds[0] = asm.load(insn.opnds[0]);
                             }
                         },
                         // We have to load memory operands to avoid corrupting them
                         (Opnd::Mem(_) | Opnd::Reg(_), _) => {
-                            *opnd = asm.load(*opnd);
+                            insn.opnds[0] = asm.load(insn.opnds[0]);
                         },
                         _ => {}
                     };
 
-                    *out = asm.next_opnd_out(Opnd::match_num_bits(&[*opnd, *shift]));
+                    insn.out = asm.next_opnd_out(Opnd::match_num_bits(&[insn.opnds[0], insn.opnds[1]]));
                     asm.push_insn(insn);
                 },
-                Insn::CSelZ { truthy, falsy, out } |
-                Insn::CSelNZ { truthy, falsy, out } |
-                Insn::CSelE { truthy, falsy, out } |
-                Insn::CSelNE { truthy, falsy, out } |
-                Insn::CSelL { truthy, falsy, out } |
-                Insn::CSelLE { truthy, falsy, out } |
-                Insn::CSelG { truthy, falsy, out } |
-                Insn::CSelGE { truthy, falsy, out } => {
+                Op::CSelZ | Op::CSelNZ | Op::CSelE | Op::CSelNE |
+                Op::CSelL | Op::CSelLE | Op::CSelG | Op::CSelGE => {
                     match unmapped_opnds[0] {
                         // If we have an instruction output whose live range
                         // spans beyond this instruction, we have to load it.
                         Opnd::InsnOut { idx, .. } => {
                             if live_ranges[idx] > index {
-                                *truthy = asm.load(*truthy);
+                                insn.opnds[0] = asm.load(insn.opnds[0]);
                             }
                         },
                         Opnd::UImm(_) | Opnd::Imm(_) | Opnd::Value(_) => {
-                            *truthy = asm.load(*truthy);
+                            insn.opnds[0] = asm.load(insn.opnds[0]);
                         },
                         _ => {}
                     };
 
-                    match falsy {
+                    match insn.opnds[1] {
                         Opnd::UImm(_) | Opnd::Imm(_) => {
-                            *falsy = asm.load(*falsy);
+                            insn.opnds[1] = asm.load(insn.opnds[1]);
                         },
                         _ => {}
                     };
 
-                    *out = asm.next_opnd_out(Opnd::match_num_bits(&[*truthy, *falsy]));
+                    insn.out = asm.next_opnd_out(Opnd::match_num_bits(&[insn.opnds[0], insn.opnds[1]]));
                     asm.push_insn(insn);
                 },
-                Insn::Mov { dest, src } => {
-                    match (&dest, &src) {
+                Op::Mov => {
+                    let dest = insn.opnds[0];
+                    let src = insn.opnds[1];
+                    match (dest, src) {
                         (Opnd::Mem(_), Opnd::Mem(_)) => {
                             // We load opnd1 because for mov, opnd0 is the output
-                            let opnd1 = asm.load(*src);
-                            asm.mov(*dest, opnd1);
+                            let opnd1 = asm.load(src);
+                            asm.mov(dest, opnd1);
                         },
                         (Opnd::Mem(_), Opnd::UImm(value)) => {
                             // 32-bit values will be sign-extended
-                            if imm_num_bits(*value as i64) > 32 {
-                                let opnd1 = asm.load(*src);
-                                asm.mov(*dest, opnd1);
+                            if imm_num_bits(value as i64) > 32 {
+                                let opnd1 = asm.load(src);
+                                asm.mov(dest, opnd1);
                             } else {
-                                asm.mov(*dest, *src);
+                                asm.mov(dest, src);
                             }
                         },
                         (Opnd::Mem(_), Opnd::Imm(value)) => {
-                            if imm_num_bits(*value) > 32 {
-                                let opnd1 = asm.load(*src);
-                                asm.mov(*dest, opnd1);
+                            if imm_num_bits(value) > 32 {
+                                let opnd1 = asm.load(src);
+                                asm.mov(dest, opnd1);
                             } else {
-                                asm.mov(*dest, *src);
+                                asm.mov(dest, src);
                             }
                         },
                         _ => {
-                            asm.mov(*dest, *src);
+                            asm.mov(dest, src);
                         }
                     }
                 },
-                Insn::Not { opnd, .. } => {
+                Op::Not => {
+                    let opnd = insn.opnds[0];
                     let opnd0 = match unmapped_opnds[0] {
                         // If we have an instruction output whose live range
                         // spans beyond this instruction, we have to load it.
                         Opnd::InsnOut { idx, .. } => {
                             if live_ranges[idx] > index {
-                                asm.load(*opnd)
+                                asm.load(opnd)
                             } else {
-                                *opnd
+                                opnd
                             }
                         },
                         // We have to load memory and register operands to avoid
                         // corrupting them.
                         Opnd::Mem(_) | Opnd::Reg(_) => {
-                            asm.load(*opnd)
+                            asm.load(opnd)
                         },
                         // Otherwise we can just reuse the existing operand.
-                        _ => *opnd
+                        _ => opnd
                     };
 
                     asm.not(opnd0);
                 },
-                Insn::CCall { opnds, fptr, .. } => {
-                    assert!(opnds.len() <= C_ARG_OPNDS.len());
+                Op::CCall => {
+                    // The SysV ABI only has six argument registers; anything
+                    // past that is passed on the C stack instead.
+                    let opnds = &insn.opnds;
+                    let (reg_opnds, stack_opnds) = if opnds.len() > C_ARG_OPNDS.len() {
+                        opnds.split_at(C_ARG_OPNDS.len())
+                    } else {
+                        (opnds.as_slice(), &[][..])
+                    };
 
-                    // Load each operand into the corresponding argument
-                    // register.
-                    for (idx, opnd) in opnds.into_iter().enumerate() {
-                        asm.load_into(C_ARG_OPNDS[idx], *opnd);
+                    // Shuffle each register operand into its argument
+                    // register, resolving any conflicts between the new
+                    // destinations and the operands' current locations.
+                    asm.reorder_c_args(reg_opnds);
+
+                    // Stack arguments are pushed right-to-left so they end
+                    // up in left-to-right order in memory. `call` itself
+                    // pushes an 8-byte return address, so an odd number of
+                    // stack arguments gets one padding slot pushed ahead of
+                    // them to keep RSP a 16-byte multiple at the call.
+                    let padded_slots = stack_opnds.len() + (stack_opnds.len() % 2);
+                    if stack_opnds.len() % 2 == 1 {
+                        asm.cpush(Opnd::UImm(0));
+                    }
+                    for &opnd in stack_opnds.iter().rev() {
+                        asm.cpush(opnd);
                     }
 
-                    // Now we push the CCall without any arguments so that it
-                    // just performs the call.
-                    asm.ccall(*fptr, vec![]);
+                    // Push the CCall with no visible operands so that it
+                    // just performs the call; stash the number of stack
+                    // bytes to reclaim afterward as its sole operand so
+                    // `x86_emit` can restore RSP once the call returns.
+                    let fptr = insn.target.unwrap().unwrap_fun_ptr();
+                    let stack_bytes = (padded_slots * 8) as u64;
+                    if stack_bytes > 0 {
+                        asm.ccall(fptr, vec![Opnd::UImm(stack_bytes)]);
+                    } else {
+                        asm.ccall(fptr, vec![]);
+                    }
+                },
+                Op::CmpXchg => {
+                    // CMPXCHG implicitly compares against and conditionally
+                    // overwrites RAX, so pin `expected` there up front the
+                    // same way CCall pins its argument registers, and
+                    // report RAX back out as the result.
+                    let (mem, expected, desired) = (insn.opnds[0], insn.opnds[1], insn.opnds[2]);
+                    asm.load_into(Opnd::Reg(RAX_REG), expected);
+                    asm.push_insn_parts(Op::CmpXchg, vec![mem, Opnd::Reg(RAX_REG), desired], None, None, None);
+                },
+                Op::AtomicCmpXchg => {
+                    // Same RAX-pinning CMPXCHG needs above, but this
+                    // variant's `out` is a materialized success flag rather
+                    // than RAX's contents, so leave `out` for x86_emit to
+                    // fill in instead of pinning it here.
+                    let (mem, expected, desired) = (insn.opnds[0], insn.opnds[1], insn.opnds[2]);
+                    asm.load_into(Opnd::Reg(RAX_REG), expected);
+                    asm.push_insn_parts(Op::AtomicCmpXchg, vec![mem, Opnd::Reg(RAX_REG), desired], None, None, None);
+                },
+                Op::GuardHeap => {
+                    // Fails (jumps to the side exit) if the value is an
+                    // immediate, or if it's one of the Qfalse/Qnil
+                    // singletons that sort below every heap pointer.
+                    let opnd = insn.opnds[0];
+                    let target = insn.target.unwrap();
+                    asm.test(opnd, Opnd::UImm(RUBY_IMMEDIATE_MASK as u64));
+                    asm.jnz(target);
+                    asm.cmp(opnd, Opnd::UImm(Qnil.into()));
+                    asm.jbe(target);
+                },
+                Op::GuardImm => {
+                    // The complement of GuardHeap: either check passing
+                    // means the value isn't a heap pointer, so skip past
+                    // the side exit jump instead of taking it.
+                    let opnd = insn.opnds[0];
+                    let target = insn.target.unwrap();
+                    let not_heap = asm.new_label("guard_imm_not_heap");
+                    asm.test(opnd, Opnd::UImm(RUBY_IMMEDIATE_MASK as u64));
+                    asm.jnz(not_heap);
+                    asm.cmp(opnd, Opnd::UImm(Qnil.into()));
+                    asm.jbe(not_heap);
+                    asm.jmp(target);
+                    asm.write_label(not_heap);
+                },
+                Op::GuardFixnum => {
+                    let opnd = insn.opnds[0];
+                    let target = insn.target.unwrap();
+                    asm.test(opnd, Opnd::UImm(RUBY_FIXNUM_FLAG as u64));
+                    asm.jz(target);
+                },
+                Op::JumpTrue => {
+                    // Truthy means neither Qfalse nor Qnil, so skip the
+                    // jump to the target unless both equality checks miss.
+                    let opnd = insn.opnds[0];
+                    let target = insn.target.unwrap();
+                    let falsy = asm.new_label("jump_true_falsy");
+                    asm.cmp(opnd, Opnd::UImm(Qfalse.into()));
+                    asm.je(falsy);
+                    asm.cmp(opnd, Opnd::UImm(Qnil.into()));
+                    asm.je(falsy);
+                    asm.jmp(target);
+                    asm.write_label(falsy);
+                },
+                Op::JumpFalse => {
+                    let opnd = insn.opnds[0];
+                    let target = insn.target.unwrap();
+                    asm.cmp(opnd, Opnd::UImm(Qfalse.into()));
+                    asm.je(target);
+                    asm.cmp(opnd, Opnd::UImm(Qnil.into()));
+                    asm.je(target);
                 },
                 _ => {
                     if insn.out_opnd().is_some() {
@@ -334,6 +643,62 @@ impl Assembler
         asm
     }
 
+    /// Pattern-match short instruction sequences over the already-split x86
+    /// IR and rewrite them to cheaper or shorter equivalents, analogous to
+    /// the Pat/DAG rewrite rules an instruction selector like LLVM's x86
+    /// backend uses. Runs between `x86_split` and `alloc_regs`, so it still
+    /// sees the one-ALU-op-per-instruction shapes `x86_split` produces.
+    /// Starter rules: `cmp x, 0` becomes the shorter `test x, x`; an `and`
+    /// immediately followed by a `cmp` of its own output against 0 has its
+    /// `cmp` dropped, since nothing ran in between to clobber the flags the
+    /// `and` already set; a `mov` into the same location it reads from is
+    /// deleted; and a `mov r, 0` becomes a dependency-breaking `xor r, r`.
+    /// Structured as a `Vec<Insn> -> Vec<Insn>` transform like `x86_split`,
+    /// so more rules can be folded in as additional match arms.
+    fn x86_peephole(self) -> Assembler
+    {
+        fn is_zero(opnd: Opnd) -> bool {
+            matches!(opnd, Opnd::Imm(0) | Opnd::UImm(0))
+        }
+
+        let mut asm = Assembler::new_with_label_names(self.label_names.clone());
+        let mut iterator = self.into_draining_iter();
+
+        while let Some((index, mut insn)) = iterator.next_unmapped() {
+            for opnd in insn.opnd_iter_mut() {
+                *opnd = iterator.map_opnd(*opnd);
+            }
+
+            match insn.op {
+                Op::Cmp if is_zero(insn.opnds[1]) => {
+                    // The `and` directly above this already set the flags
+                    // this `cmp` is testing for, so drop it outright.
+                    let left = insn.opnds[0];
+                    let redundant = matches!(
+                        asm.insns.last(),
+                        Some(last) if last.op == Op::And && last.out == left
+                    );
+
+                    if !redundant {
+                        asm.test(left, left);
+                    }
+                },
+                Op::Mov if insn.opnds[0] == insn.opnds[1] => {},
+                Op::Mov if is_zero(insn.opnds[1]) && matches!(insn.opnds[0], Opnd::Reg(_)) => {
+                    let dest = insn.opnds[0];
+                    asm.xor(dest, dest);
+                },
+                _ => {
+                    asm.push_insn(insn);
+                }
+            };
+
+            iterator.map_insn_index(&mut asm);
+        }
+
+        asm
+    }
+
     /// Emit platform-specific machine code
     pub fn x86_emit(&mut self, cb: &mut CodeBlock) -> Vec<u32>
     {
@@ -379,7 +744,6 @@ impl Assembler
         let mut gc_offsets: Vec<u32> = Vec::new();
 
         // For each instruction
-        let start_write_pos = cb.get_write_pos();
         let mut insns_idx: usize = 0;
         while let Some(insn) = self.insns.get(insns_idx) {
             let src_ptr = cb.get_write_ptr();
@@ -387,25 +751,30 @@ impl Assembler
             let old_label_state = cb.get_label_state();
             let mut insn_gc_offsets: Vec<u32> = Vec::new();
 
-            match insn {
-                Insn::Comment(text) => {
+            match insn.op {
+                Op::Comment => {
                     if cfg!(feature = "disasm") {
-                        cb.add_comment(text);
+                        cb.add_comment(insn.text.as_ref().unwrap());
                     }
                 },
 
                 // Write the label at the current position
-                Insn::Label(target) => {
-                    cb.write_label(target.unwrap_label_idx());
+                Op::Label => {
+                    cb.write_label(insn.target.unwrap().unwrap_label_idx());
                 },
 
                 // Report back the current position in the generated code
-                Insn::PosMarker(pos_marker) => {
-                    pos_marker(cb.get_write_ptr());
+                Op::PosMarker => {
+                    // `pos_marker` is a `FnOnce`, so it can only be called by
+                    // value; `self.insns` only hands out shared references,
+                    // so take it the same way register allocation output
+                    // would otherwise be mutated -- through the raw index.
+                    let marker = self.insns[insns_idx].pos_marker.take().unwrap();
+                    marker(cb.get_write_ptr());
                 },
 
-                Insn::BakeString(text) => {
-                    for byte in text.as_bytes() {
+                Op::BakeString => {
+                    for byte in insn.text.as_ref().unwrap().as_bytes() {
                         cb.write_byte(*byte);
                     }
 
@@ -414,57 +783,107 @@ impl Assembler
                     cb.write_byte(0);
                 },
 
-                Insn::Add { left, right, .. } => {
-                    let opnd1 = emit_64bit_immediate(cb, right);
-                    add(cb, left.into(), opnd1);
+                Op::Add => {
+                    let right = insn.opnds[1];
+                    let opnd1 = emit_64bit_immediate(cb, &right);
+                    add(cb, insn.opnds[0].into(), opnd1);
+                },
+
+                Op::FrameSetup => {},
+                Op::FrameTeardown => {},
+
+                Op::Sub => {
+                    let right = insn.opnds[1];
+                    let opnd1 = emit_64bit_immediate(cb, &right);
+                    sub(cb, insn.opnds[0].into(), opnd1);
+                },
+
+                Op::And => {
+                    let right = insn.opnds[1];
+                    let opnd1 = emit_64bit_immediate(cb, &right);
+                    and(cb, insn.opnds[0].into(), opnd1);
+                },
+
+                Op::Or => {
+                    let right = insn.opnds[1];
+                    let opnd1 = emit_64bit_immediate(cb, &right);
+                    or(cb, insn.opnds[0].into(), opnd1);
+                },
+
+                Op::Xor => {
+                    let right = insn.opnds[1];
+                    let opnd1 = emit_64bit_immediate(cb, &right);
+                    xor(cb, insn.opnds[0].into(), opnd1);
                 },
 
-                Insn::FrameSetup => {},
-                Insn::FrameTeardown => {},
+                Op::Mul => {
+                    let right = insn.opnds[1];
+                    let opnd1 = emit_64bit_immediate(cb, &right);
+                    imul(cb, insn.opnds[0].into(), opnd1);
+                },
 
-                Insn::Sub { left, right, .. } => {
-                    let opnd1 = emit_64bit_immediate(cb, right);
-                    sub(cb, left.into(), opnd1);
+                // One-operand forms: `x86_split` already pinned `left` into
+                // RAX, so the only operand left to encode is the multiplier.
+                Op::SMul => {
+                    imul1(cb, insn.opnds[1].into());
+                },
+                Op::UMul => {
+                    mul1(cb, insn.opnds[1].into());
                 },
 
-                Insn::And { left, right, .. } => {
-                    let opnd1 = emit_64bit_immediate(cb, right);
-                    and(cb, left.into(), opnd1);
+                // `x86_split` already pinned RDX:RAX to the dividend, so the
+                // only operand left to encode is the divisor.
+                Op::SDiv | Op::SMod => {
+                    idiv(cb, insn.opnds[1].into());
+                },
+                Op::UDiv | Op::UMod => {
+                    div(cb, insn.opnds[1].into());
                 },
 
-                Insn::Or { left, right, .. } => {
-                    let opnd1 = emit_64bit_immediate(cb, right);
-                    or(cb, left.into(), opnd1);
+                Op::FAdd => {
+                    addsd(cb, insn.opnds[0].into(), insn.opnds[1].into());
                 },
 
-                Insn::Xor { left, right, .. } => {
-                    let opnd1 = emit_64bit_immediate(cb, right);
-                    xor(cb, left.into(), opnd1);
+                Op::FSub => {
+                    subsd(cb, insn.opnds[0].into(), insn.opnds[1].into());
                 },
 
-                Insn::Not { opnd, .. } => {
-                    not(cb, opnd.into());
+                Op::FMul => {
+                    mulsd(cb, insn.opnds[0].into(), insn.opnds[1].into());
                 },
 
-                Insn::LShift { opnd, shift , ..} => {
-                    shl(cb, opnd.into(), shift.into())
+                Op::FDiv => {
+                    divsd(cb, insn.opnds[0].into(), insn.opnds[1].into());
                 },
 
-                Insn::RShift { opnd, shift , ..} => {
-                    sar(cb, opnd.into(), shift.into())
+                Op::Not => {
+                    not(cb, insn.opnds[0].into());
                 },
 
-                Insn::URShift { opnd, shift, .. } => {
-                    shr(cb, opnd.into(), shift.into())
+                Op::LShift => {
+                    shl(cb, insn.opnds[0].into(), insn.opnds[1].into())
                 },
 
-                Insn::Store { dest, src } => {
-                    mov(cb, dest.into(), src.into());
+                Op::RShift => {
+                    sar(cb, insn.opnds[0].into(), insn.opnds[1].into())
+                },
+
+                Op::URShift => {
+                    shr(cb, insn.opnds[0].into(), insn.opnds[1].into())
+                },
+
+                Op::Store => {
+                    mov(cb, insn.opnds[0].into(), insn.opnds[1].into());
+                },
+
+                Op::FStore => {
+                    movsd(cb, insn.opnds[0].into(), insn.opnds[1].into());
                 },
 
                 // This assumes only load instructions can contain references to GC'd Value operands
-                Insn::Load { opnd, out } |
-                Insn::LoadInto { dest: out, opnd } => {
+                Op::Load => {
+                    let opnd = insn.opnds[0];
+                    let out = insn.out;
                     match opnd {
                         Opnd::Value(val) if val.heap_object_p() => {
                             // Using movabs because mov might write value in 32 bits
@@ -477,22 +896,27 @@ impl Assembler
                     }
                 },
 
-                Insn::LoadSExt { opnd, out } => {
-                    movsx(cb, out.into(), opnd.into());
+                Op::LoadSExt => {
+                    movsx(cb, insn.out.into(), insn.opnds[0].into());
                 },
 
-                Insn::Mov { dest, src } => {
-                    mov(cb, dest.into(), src.into());
+                Op::FLoad => {
+                    movsd(cb, insn.out.into(), insn.opnds[0].into());
+                },
+
+                Op::Mov => {
+                    mov(cb, insn.opnds[0].into(), insn.opnds[1].into());
                 },
 
                 // Load effective address
-                Insn::Lea { opnd, out } => {
-                    lea(cb, out.into(), opnd.into());
+                Op::Lea => {
+                    lea(cb, insn.out.into(), insn.opnds[0].into());
                 },
 
                 // Load relative address
-                Insn::LeaLabel { target, out } => {
-                    let label_idx = target.unwrap_label_idx();
+                Op::LeaLabel => {
+                    let label_idx = insn.target.unwrap().unwrap_label_idx();
+                    let out = insn.out;
 
                     cb.label_ref(label_idx, 7, |cb, src_addr, dst_addr| {
                         let disp = dst_addr - src_addr;
@@ -503,19 +927,19 @@ impl Assembler
                 },
 
                 // Push and pop to/from the C stack
-                Insn::CPush(opnd) => {
-                    push(cb, opnd.into());
+                Op::CPush => {
+                    push(cb, insn.opnds[0].into());
                 },
-                Insn::CPop { out } => {
-                    pop(cb, out.into());
+                Op::CPop => {
+                    pop(cb, insn.out.into());
                 },
-                Insn::CPopInto(opnd) => {
-                    pop(cb, opnd.into());
+                Op::CPopInto => {
+                    pop(cb, insn.opnds[0].into());
                 },
 
                 // Push and pop to the C stack all caller-save registers and the
                 // flags
-                Insn::CPushAll => {
+                Op::CPushAll => {
                     let regs = Assembler::get_caller_save_regs();
 
                     for reg in regs {
@@ -523,7 +947,7 @@ impl Assembler
                     }
                     pushfq(cb);
                 },
-                Insn::CPopAll => {
+                Op::CPopAll => {
                     let regs = Assembler::get_caller_save_regs();
 
                     popfq(cb);
@@ -533,13 +957,22 @@ impl Assembler
                 },
 
                 // C function call
-                Insn::CCall { fptr, .. } => {
-                    call_ptr(cb, RAX, *fptr);
+                Op::CCall => {
+                    let fptr = insn.target.unwrap().unwrap_fun_ptr();
+                    call_ptr(cb, RAX, fptr);
+
+                    // Reclaim whatever `x86_split` pushed for the seventh
+                    // argument onward (plus alignment padding), restoring
+                    // RSP to where it was before those pushes.
+                    if let Some(Opnd::UImm(stack_bytes)) = insn.opnds.first() {
+                        add(cb, RSP, uimm_opnd(*stack_bytes));
+                    }
                 },
 
-                Insn::CRet(opnd) => {
+                Op::CRet => {
+                    let opnd = insn.opnds[0];
                     // TODO: bias allocation towards return register
-                    if *opnd != Opnd::Reg(C_RET_REG) {
+                    if opnd != Opnd::Reg(C_RET_REG) {
                         mov(cb, RAX, opnd.into());
                     }
 
@@ -547,10 +980,12 @@ impl Assembler
                 },
 
                 // Compare
-                Insn::Cmp { left, right } => {
+                Op::Cmp => {
+                    let left = insn.opnds[0];
+                    let right = insn.opnds[1];
                     let num_bits = match right {
-                        Opnd::Imm(value) => Some(imm_num_bits(*value)),
-                        Opnd::UImm(value) => Some(uimm_num_bits(*value)),
+                        Opnd::Imm(value) => Some(imm_num_bits(value)),
+                        Opnd::UImm(value) => Some(uimm_num_bits(value)),
                         _ => None
                     };
 
@@ -563,125 +998,216 @@ impl Assembler
                     if num_bits.is_some() && left.num_bits() == num_bits && num_bits.unwrap() < 64 {
                         cmp(cb, left.into(), right.into());
                     } else {
-                        let emitted = emit_64bit_immediate(cb, right);
+                        let emitted = emit_64bit_immediate(cb, &right);
                         cmp(cb, left.into(), emitted);
                     }
                 }
 
                 // Test and set flags
-                Insn::Test { left, right } => {
-                    let emitted = emit_64bit_immediate(cb, right);
-                    test(cb, left.into(), emitted);
+                Op::Test => {
+                    let right = insn.opnds[1];
+                    let emitted = emit_64bit_immediate(cb, &right);
+                    test(cb, insn.opnds[0].into(), emitted);
+                }
+
+                // Compare two doubles and set flags the same way `Cmp` does for integers
+                Op::FCmp => {
+                    ucomisd(cb, insn.opnds[0].into(), insn.opnds[1].into());
                 }
 
-                Insn::JmpOpnd(opnd) => {
-                    jmp_rm(cb, opnd.into());
+                Op::JmpOpnd => {
+                    jmp_rm(cb, insn.opnds[0].into());
                 }
 
                 // Conditional jump to a label
-                Insn::Jmp(target) => {
-                    match *target {
-                        Target::CodePtr(code_ptr) | Target::SideExitPtr(code_ptr) => jmp_ptr(cb, code_ptr),
+                Op::Jmp => {
+                    match insn.target.unwrap() {
+                        Target::CodePtr(code_ptr) => jmp_ptr(cb, code_ptr),
                         Target::Label(label_idx) => jmp_label(cb, label_idx),
+                        Target::FunPtr(_) => unreachable!("Jmp can't target a raw C function pointer"),
                     }
                 }
 
-                Insn::Je(target) => {
-                    match *target {
-                        Target::CodePtr(code_ptr) | Target::SideExitPtr(code_ptr) => je_ptr(cb, code_ptr),
+                Op::Je => {
+                    match insn.target.unwrap() {
+                        Target::CodePtr(code_ptr) => je_ptr(cb, code_ptr),
                         Target::Label(label_idx) => je_label(cb, label_idx),
+                        Target::FunPtr(_) => unreachable!("Je can't target a raw C function pointer"),
                     }
                 }
 
-                Insn::Jne(target) => {
-                    match *target {
-                        Target::CodePtr(code_ptr) | Target::SideExitPtr(code_ptr) => jne_ptr(cb, code_ptr),
+                Op::Jne => {
+                    match insn.target.unwrap() {
+                        Target::CodePtr(code_ptr) => jne_ptr(cb, code_ptr),
                         Target::Label(label_idx) => jne_label(cb, label_idx),
+                        Target::FunPtr(_) => unreachable!("Jne can't target a raw C function pointer"),
                     }
                 }
 
-                Insn::Jl(target) => {
-                    match *target {
-                        Target::CodePtr(code_ptr) | Target::SideExitPtr(code_ptr) => jl_ptr(cb, code_ptr),
+                Op::Jl => {
+                    match insn.target.unwrap() {
+                        Target::CodePtr(code_ptr) => jl_ptr(cb, code_ptr),
                         Target::Label(label_idx) => jl_label(cb, label_idx),
+                        Target::FunPtr(_) => unreachable!("Jl can't target a raw C function pointer"),
                     }
                 },
 
-                Insn::Jbe(target) => {
-                    match *target {
-                        Target::CodePtr(code_ptr) | Target::SideExitPtr(code_ptr) => jbe_ptr(cb, code_ptr),
+                Op::Jbe => {
+                    match insn.target.unwrap() {
+                        Target::CodePtr(code_ptr) => jbe_ptr(cb, code_ptr),
                         Target::Label(label_idx) => jbe_label(cb, label_idx),
+                        Target::FunPtr(_) => unreachable!("Jbe can't target a raw C function pointer"),
                     }
                 },
 
-                Insn::Jz(target) => {
-                    match *target {
-                        Target::CodePtr(code_ptr) | Target::SideExitPtr(code_ptr) => jz_ptr(cb, code_ptr),
+                Op::Jz => {
+                    match insn.target.unwrap() {
+                        Target::CodePtr(code_ptr) => jz_ptr(cb, code_ptr),
                         Target::Label(label_idx) => jz_label(cb, label_idx),
+                        Target::FunPtr(_) => unreachable!("Jz can't target a raw C function pointer"),
                     }
                 }
 
-                Insn::Jnz(target) => {
-                    match *target {
-                        Target::CodePtr(code_ptr) | Target::SideExitPtr(code_ptr) => jnz_ptr(cb, code_ptr),
+                Op::Jnz => {
+                    match insn.target.unwrap() {
+                        Target::CodePtr(code_ptr) => jnz_ptr(cb, code_ptr),
                         Target::Label(label_idx) => jnz_label(cb, label_idx),
+                        Target::FunPtr(_) => unreachable!("Jnz can't target a raw C function pointer"),
                     }
                 }
 
-                Insn::Jo(target) => {
-                    match *target {
-                        Target::CodePtr(code_ptr) | Target::SideExitPtr(code_ptr) => jo_ptr(cb, code_ptr),
+                Op::Jo => {
+                    match insn.target.unwrap() {
+                        Target::CodePtr(code_ptr) => jo_ptr(cb, code_ptr),
                         Target::Label(label_idx) => jo_label(cb, label_idx),
+                        Target::FunPtr(_) => unreachable!("Jo can't target a raw C function pointer"),
                     }
                 }
 
                 // Atomically increment a counter at a given memory location
-                Insn::IncrCounter { mem, value } => {
+                Op::IncrCounter => {
+                    let mem = insn.opnds[0];
+                    let value = insn.opnds[1];
                     assert!(matches!(mem, Opnd::Mem(_)));
                     assert!(matches!(value, Opnd::UImm(_) | Opnd::Imm(_) ) );
                     write_lock_prefix(cb);
                     add(cb, mem.into(), value.into());
                 },
 
-                Insn::Breakpoint => int3(cb),
+                // Atomic fetch-and-add: XADD leaves the pre-add value in
+                // `val`'s register, which is then copied into `out`, so a
+                // shared profiling counter can be bumped without a separate
+                // load.
+                Op::AtomicAdd => {
+                    let mem = insn.opnds[0];
+                    let val = insn.opnds[1];
+                    assert!(matches!(mem, Opnd::Mem(_)));
+                    write_lock_prefix(cb);
+                    xadd(cb, mem.into(), val.into());
+                    if insn.out != Opnd::None && insn.out != val {
+                        mov(cb, insn.out.into(), val.into());
+                    }
+                },
+
+                // Atomic compare-and-swap. `expected` was pinned to RAX by
+                // x86_split, matching CMPXCHG's implicit RAX operand, and
+                // RAX (the value found in memory) is copied into `out`
+                // afterward; `mov` leaves the resulting ZF for a following
+                // `Je`/`Jne` to branch on.
+                Op::CmpXchg => {
+                    let mem = insn.opnds[0];
+                    let expected = insn.opnds[1];
+                    let desired = insn.opnds[2];
+                    assert!(matches!(mem, Opnd::Mem(_)));
+                    assert_eq!(expected, Opnd::Reg(RAX_REG));
+                    write_lock_prefix(cb);
+                    cmpxchg(cb, mem.into(), desired.into());
+                    if insn.out != Opnd::None && insn.out != Opnd::Reg(RAX_REG) {
+                        mov(cb, insn.out.into(), Opnd::Reg(RAX_REG).into());
+                    }
+                },
+
+                // Atomic fetch-and-subtract: x86-64 has no atomic-subtract
+                // encoding, so negate `val`'s register in place first and
+                // reuse the same LOCK XADD fetch-and-add does -- XADD then
+                // leaves the pre-subtract value in `val`'s register and the
+                // post-subtract value in memory.
+                Op::AtomicSub => {
+                    let mem = insn.opnds[0];
+                    let val = insn.opnds[1];
+                    assert!(matches!(mem, Opnd::Mem(_)));
+                    neg(cb, val.into());
+                    write_lock_prefix(cb);
+                    xadd(cb, mem.into(), val.into());
+                    if insn.out != Opnd::None && insn.out != val {
+                        mov(cb, insn.out.into(), val.into());
+                    }
+                },
+
+                // XCHG with a memory operand is implicitly locked, so no
+                // explicit LOCK prefix is needed (unlike XADD/CMPXCHG). The
+                // old memory value it leaves in `val`'s register is copied
+                // into `out`.
+                Op::AtomicXchg => {
+                    let mem = insn.opnds[0];
+                    let val = insn.opnds[1];
+                    assert!(matches!(mem, Opnd::Mem(_)));
+                    xchg(cb, mem.into(), val.into());
+                    if insn.out != Opnd::None && insn.out != val {
+                        mov(cb, insn.out.into(), val.into());
+                    }
+                },
+
+                // Same CMPXCHG as above, but the caller only wants to know
+                // whether the swap took, so materialize the resulting ZF
+                // into `out` (zeroed first since SETE only writes the low
+                // byte) instead of reporting RAX's contents.
+                Op::AtomicCmpXchg => {
+                    let mem = insn.opnds[0];
+                    let expected = insn.opnds[1];
+                    let desired = insn.opnds[2];
+                    let out = insn.out;
+                    assert!(matches!(mem, Opnd::Mem(_)));
+                    assert_eq!(expected, Opnd::Reg(RAX_REG));
+                    write_lock_prefix(cb);
+                    cmpxchg(cb, mem.into(), desired.into());
+                    // `mov`, unlike `xor`, leaves ZF as CMPXCHG set it.
+                    mov(cb, out.into(), uimm_opnd(0));
+                    sete(cb, out.into());
+                },
+
+                Op::Breakpoint => int3(cb),
 
-                Insn::CSelZ { truthy, falsy, out } => {
-                    emit_csel(cb, *truthy, *falsy, *out, cmovnz);
+                Op::CSelZ => {
+                    emit_csel(cb, insn.opnds[0], insn.opnds[1], insn.out, cmovnz);
                 },
-                Insn::CSelNZ { truthy, falsy, out } => {
-                    emit_csel(cb, *truthy, *falsy, *out, cmovz);
+                Op::CSelNZ => {
+                    emit_csel(cb, insn.opnds[0], insn.opnds[1], insn.out, cmovz);
                 },
-                Insn::CSelE { truthy, falsy, out } => {
-                    emit_csel(cb, *truthy, *falsy, *out, cmovne);
+                Op::CSelE => {
+                    emit_csel(cb, insn.opnds[0], insn.opnds[1], insn.out, cmovne);
                 },
-                Insn::CSelNE { truthy, falsy, out } => {
-                    emit_csel(cb, *truthy, *falsy, *out, cmove);
+                Op::CSelNE => {
+                    emit_csel(cb, insn.opnds[0], insn.opnds[1], insn.out, cmove);
                 },
-                Insn::CSelL { truthy, falsy, out } => {
-                    emit_csel(cb, *truthy, *falsy, *out, cmovge);
+                Op::CSelL => {
+                    emit_csel(cb, insn.opnds[0], insn.opnds[1], insn.out, cmovge);
                 },
-                Insn::CSelLE { truthy, falsy, out } => {
-                    emit_csel(cb, *truthy, *falsy, *out, cmovg);
+                Op::CSelLE => {
+                    emit_csel(cb, insn.opnds[0], insn.opnds[1], insn.out, cmovg);
                 },
-                Insn::CSelG { truthy, falsy, out } => {
-                    emit_csel(cb, *truthy, *falsy, *out, cmovle);
+                Op::CSelG => {
+                    emit_csel(cb, insn.opnds[0], insn.opnds[1], insn.out, cmovle);
                 },
-                Insn::CSelGE { truthy, falsy, out } => {
-                    emit_csel(cb, *truthy, *falsy, *out, cmovl);
-                }
-                Insn::LiveReg { .. } => (), // just a reg alloc signal, no code
-                Insn::PadInvalPatch => {
-                    let code_size = cb.get_write_pos().saturating_sub(std::cmp::max(start_write_pos, cb.page_start_pos()));
-                    if code_size < JMP_PTR_BYTES {
-                        nop(cb, (JMP_PTR_BYTES - code_size) as u32);
-                    }
+                Op::CSelGE => {
+                    emit_csel(cb, insn.opnds[0], insn.opnds[1], insn.out, cmovl);
                 }
+                Op::LiveReg => (), // just a reg alloc signal, no code
 
                 // We want to keep the panic here because some instructions that
                 // we feed to the backend could get lowered into other
                 // instructions. So it's possible that some of our backend
                 // instructions can never make it to the emit stage.
-                #[allow(unreachable_patterns)]
                 _ => panic!("unsupported instruction passed to x86 backend: {:?}", insn)
             };
 
@@ -698,10 +1224,136 @@ impl Assembler
         gc_offsets
     }
 
-    /// Optimize and compile the stored instructions
-    pub fn compile_with_regs(self, cb: &mut CodeBlock, regs: Vec<Reg>) -> Vec<u32>
+    /// Lower the already-split, already-register-allocated instruction
+    /// list to the compact bytecode `Interpreter` executes, instead of to
+    /// native x86-64. Mirrors `x86_emit`'s shape -- one pass over
+    /// `self.insns` -- but every operand is resolved to an `IOpnd` rather
+    /// than encoded, and label targets are resolved to bytecode offsets
+    /// ahead of time since there's no linker pass to backpatch them later.
+    ///
+    /// Only the ops a real differential test would hit are modeled:
+    /// arithmetic/logic, `Cmp`/`Test`, the `CSel*` family, plain
+    /// `Load`/`Store`/`Mov`, the unconditional/conditional jumps, the
+    /// atomic read-modify-write ops, and `CRet`. Anything else (`CCall`,
+    /// `Lea`, the C-stack push/pop family, floating-point ops, ...)
+    /// lowers to `IInsn::Unsupported` and panics if actually executed,
+    /// rather than silently producing the wrong state.
+    pub fn interp_emit(&mut self) -> Vec<IInsn>
+    {
+        // `Insn::Label` markers don't themselves lower to a bytecode
+        // instruction, so a forward branch needs to know where every
+        // label ends up before anything is translated -- the same
+        // two-pass trick a textbook assembler uses to resolve forward
+        // references.
+        let mut label_offsets = vec![0usize; self.label_names.len()];
+        let mut offset = 0;
+        for insn in &self.insns {
+            match insn.op {
+                Op::Label => label_offsets[insn.target.unwrap().unwrap_label_idx()] = offset,
+                Op::Comment | Op::PosMarker => {},
+                _ => offset += 1,
+            }
+        }
+
+        let resolve_target = |target: &Target| -> ITarget {
+            match target {
+                Target::Label(idx) => ITarget::Offset(label_offsets[*idx]),
+                _ => ITarget::Halt(*target),
+            }
+        };
+
+        let mut prog = Vec::with_capacity(offset);
+
+        for insn in &self.insns {
+            let (left, right) = (insn.opnds.first().copied(), insn.opnds.get(1).copied());
+            let out = insn.out;
+
+            match insn.op {
+                Op::Comment | Op::PosMarker | Op::Label => {},
+
+                Op::Add => prog.push(IInsn::Add { left: left.unwrap().into(), right: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::Sub => prog.push(IInsn::Sub { left: left.unwrap().into(), right: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::And => prog.push(IInsn::And { left: left.unwrap().into(), right: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::Or => prog.push(IInsn::Or { left: left.unwrap().into(), right: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::Xor => prog.push(IInsn::Xor { left: left.unwrap().into(), right: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::Mul => prog.push(IInsn::Mul { left: left.unwrap().into(), right: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::SMul => prog.push(IInsn::SMul { left: left.unwrap().into(), right: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::UMul => prog.push(IInsn::UMul { left: left.unwrap().into(), right: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::SDiv => prog.push(IInsn::SDiv { left: left.unwrap().into(), right: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::UDiv => prog.push(IInsn::UDiv { left: left.unwrap().into(), right: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::SMod => prog.push(IInsn::SMod { left: left.unwrap().into(), right: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::UMod => prog.push(IInsn::UMod { left: left.unwrap().into(), right: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::Not => prog.push(IInsn::Not { opnd: left.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::LShift => prog.push(IInsn::LShift { opnd: left.unwrap().into(), shift: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::RShift => prog.push(IInsn::RShift { opnd: left.unwrap().into(), shift: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::URShift => prog.push(IInsn::URShift { opnd: left.unwrap().into(), shift: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+
+                Op::Mov => prog.push(IInsn::Mov { dest: left.unwrap().into(), src: right.unwrap().into() }),
+                Op::Load => prog.push(IInsn::Load { opnd: left.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::LoadSExt => prog.push(IInsn::LoadSExt { opnd: left.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::Store => prog.push(IInsn::Store { dest: left.unwrap().into(), src: right.unwrap().into() }),
+
+                Op::Cmp => prog.push(IInsn::Cmp { left: left.unwrap().into(), right: right.unwrap().into() }),
+                Op::Test => prog.push(IInsn::Test { left: left.unwrap().into(), right: right.unwrap().into() }),
+
+                Op::IncrCounter => prog.push(IInsn::IncrCounter { mem: left.unwrap().into(), value: right.unwrap().into() }),
+                Op::AtomicAdd => prog.push(IInsn::AtomicAdd { mem: left.unwrap().into(), val: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::AtomicSub => prog.push(IInsn::AtomicSub { mem: left.unwrap().into(), val: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::AtomicXchg => prog.push(IInsn::AtomicXchg { mem: left.unwrap().into(), val: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::CmpXchg => prog.push(IInsn::CmpXchg { mem: left.unwrap().into(), expected: right.unwrap().into(), desired: insn.opnds[2].into(), out: out.unwrap_reg().reg_no }),
+                Op::AtomicCmpXchg => prog.push(IInsn::AtomicCmpXchg { mem: left.unwrap().into(), expected: right.unwrap().into(), desired: insn.opnds[2].into(), out: out.unwrap_reg().reg_no }),
+
+                Op::CSelZ => prog.push(IInsn::CSel { cond: ICond::Z, truthy: left.unwrap().into(), falsy: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::CSelNZ => prog.push(IInsn::CSel { cond: ICond::Nz, truthy: left.unwrap().into(), falsy: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::CSelE => prog.push(IInsn::CSel { cond: ICond::E, truthy: left.unwrap().into(), falsy: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::CSelNE => prog.push(IInsn::CSel { cond: ICond::Ne, truthy: left.unwrap().into(), falsy: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::CSelL => prog.push(IInsn::CSel { cond: ICond::L, truthy: left.unwrap().into(), falsy: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::CSelLE => prog.push(IInsn::CSel { cond: ICond::Le, truthy: left.unwrap().into(), falsy: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::CSelG => prog.push(IInsn::CSel { cond: ICond::G, truthy: left.unwrap().into(), falsy: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+                Op::CSelGE => prog.push(IInsn::CSel { cond: ICond::Ge, truthy: left.unwrap().into(), falsy: right.unwrap().into(), out: out.unwrap_reg().reg_no }),
+
+                Op::Jmp => prog.push(IInsn::Jmp(resolve_target(&insn.target.unwrap()))),
+                Op::Jl => prog.push(IInsn::Jcc(ICond::L, resolve_target(&insn.target.unwrap()))),
+                Op::Jbe => prog.push(IInsn::Jcc(ICond::Be, resolve_target(&insn.target.unwrap()))),
+                Op::Je => prog.push(IInsn::Jcc(ICond::E, resolve_target(&insn.target.unwrap()))),
+                Op::Jne => prog.push(IInsn::Jcc(ICond::Ne, resolve_target(&insn.target.unwrap()))),
+                Op::Jz => prog.push(IInsn::Jcc(ICond::Z, resolve_target(&insn.target.unwrap()))),
+                Op::Jnz => prog.push(IInsn::Jcc(ICond::Nz, resolve_target(&insn.target.unwrap()))),
+                Op::Jo => prog.push(IInsn::Jcc(ICond::O, resolve_target(&insn.target.unwrap()))),
+
+                Op::CRet => prog.push(IInsn::Ret(left.unwrap().into())),
+
+                Op::FrameSetup | Op::FrameTeardown | Op::Breakpoint | Op::LiveReg => {},
+
+                _ => prog.push(IInsn::Unsupported(insn.op)),
+            }
+        }
+
+        prog
+    }
+
+    /// Run the full lowering pipeline (generic peephole and splitting,
+    /// platform splitting and peephole, register allocation) and lower the
+    /// result to interpreter bytecode instead of emitting native code.
+    /// Shares every pass with `compile_with_regs` up through `alloc_regs`
+    /// so the two only ever diverge at the final emission step -- the
+    /// differential test mode described on `interp_emit` depends on both
+    /// paths lowering the same program the same way.
+    pub fn compile_with_interp(self, regs: Vec<Reg>) -> Vec<IInsn>
     {
-        let mut asm = self.x86_split().alloc_regs(regs);
+        self.peephole().split_insns().x86_split().x86_peephole().alloc_regs(regs).interp_emit()
+    }
+
+    /// Run the full lowering pipeline (generic peephole and splitting,
+    /// platform splitting and peephole, register allocation) and emit the
+    /// final x86-64 encoding for every instruction, returning the entry
+    /// `CodePtr` of the emitted code alongside the list of GC offsets.
+    /// Label targets are backpatched by `CodeBlock::link_labels()` once every
+    /// instruction (and therefore every label position) has been written, so
+    /// forward jumps resolve correctly.
+    pub fn compile_with_regs(self, cb: &mut CodeBlock, regs: Vec<Reg>) -> (CodePtr, Vec<u32>)
+    {
+        let mut asm = self.peephole().split_insns().x86_split().x86_peephole().alloc_regs(regs);
 
         // Create label instances in the code block
         for (idx, name) in asm.label_names.iter().enumerate() {
@@ -709,6 +1361,7 @@ impl Assembler
             assert!(label_idx == idx);
         }
 
+        let start_ptr = cb.get_write_ptr();
         let gc_offsets = asm.x86_emit(cb);
 
         if cb.has_dropped_bytes() {
@@ -717,7 +1370,316 @@ impl Assembler
             cb.link_labels();
         }
 
-        gc_offsets
+        (start_ptr, gc_offsets)
+    }
+}
+
+/// A fully-resolved instruction operand in `interp_emit`'s bytecode --
+/// the `IOpnd` counterpart of `Opnd` once register allocation has settled
+/// every `InsnOut` onto a concrete register or stack slot.
+#[derive(Clone, Copy, Debug)]
+pub enum IOpnd {
+    Imm(i64),
+    Reg(u8),
+    Mem { base_reg: u8, disp: i32 },
+}
+
+impl From<Opnd> for IOpnd {
+    fn from(opnd: Opnd) -> Self {
+        match opnd {
+            Opnd::Imm(val) => IOpnd::Imm(val),
+            Opnd::UImm(val) => IOpnd::Imm(val as i64),
+            Opnd::Value(VALUE(uimm)) => IOpnd::Imm(uimm as i64),
+            Opnd::Reg(reg) => IOpnd::Reg(reg.reg_no),
+            Opnd::Mem(Mem { base: MemBase::Reg(reg_no), disp, .. }) => IOpnd::Mem { base_reg: reg_no, disp },
+            Opnd::InsnOut { .. } => panic!("InsnOut operand made it past register allocation"),
+            _ => panic!("unsupported interpreter operand: {opnd:?}"),
+        }
+    }
+}
+
+/// The flag-based condition a `Jcc`/`CSel*` instruction branches or
+/// selects on. Evaluated directly against `Flags` at branch time rather
+/// than by replaying the inverted-cmov trick `x86_emit`'s `emit_csel`
+/// uses -- that inversion is an artifact of how `cmov` composes with an
+/// unconditional `mov`, not part of the condition's actual meaning.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ICond { Z, Nz, E, Ne, L, Le, G, Ge, Be, O }
+
+/// A resolved branch target: either a bytecode offset (for `Target::Label`)
+/// or a halt carrying the original `Target`, for the `CodePtr`/`FunPtr`
+/// targets a real side exit or C call would jump to.
+#[derive(Clone, Copy, Debug)]
+pub enum ITarget {
+    Offset(usize),
+    Halt(Target),
+}
+
+/// One instruction of the compact register-machine bytecode `interp_emit`
+/// produces from the post-split, post-register-allocation IR.
+#[derive(Clone, Debug)]
+pub enum IInsn {
+    Add { left: IOpnd, right: IOpnd, out: u8 },
+    Sub { left: IOpnd, right: IOpnd, out: u8 },
+    And { left: IOpnd, right: IOpnd, out: u8 },
+    Or { left: IOpnd, right: IOpnd, out: u8 },
+    Xor { left: IOpnd, right: IOpnd, out: u8 },
+    Mul { left: IOpnd, right: IOpnd, out: u8 },
+    SMul { left: IOpnd, right: IOpnd, out: u8 },
+    UMul { left: IOpnd, right: IOpnd, out: u8 },
+    SDiv { left: IOpnd, right: IOpnd, out: u8 },
+    UDiv { left: IOpnd, right: IOpnd, out: u8 },
+    SMod { left: IOpnd, right: IOpnd, out: u8 },
+    UMod { left: IOpnd, right: IOpnd, out: u8 },
+    Not { opnd: IOpnd, out: u8 },
+    LShift { opnd: IOpnd, shift: IOpnd, out: u8 },
+    RShift { opnd: IOpnd, shift: IOpnd, out: u8 },
+    URShift { opnd: IOpnd, shift: IOpnd, out: u8 },
+    Mov { dest: IOpnd, src: IOpnd },
+    Load { opnd: IOpnd, out: u8 },
+    LoadSExt { opnd: IOpnd, out: u8 },
+    Store { dest: IOpnd, src: IOpnd },
+    Cmp { left: IOpnd, right: IOpnd },
+    Test { left: IOpnd, right: IOpnd },
+    IncrCounter { mem: IOpnd, value: IOpnd },
+    AtomicAdd { mem: IOpnd, val: IOpnd, out: u8 },
+    AtomicSub { mem: IOpnd, val: IOpnd, out: u8 },
+    AtomicXchg { mem: IOpnd, val: IOpnd, out: u8 },
+    CmpXchg { mem: IOpnd, expected: IOpnd, desired: IOpnd, out: u8 },
+    AtomicCmpXchg { mem: IOpnd, expected: IOpnd, desired: IOpnd, out: u8 },
+    CSel { cond: ICond, truthy: IOpnd, falsy: IOpnd, out: u8 },
+    Jmp(ITarget),
+    Jcc(ICond, ITarget),
+    Ret(IOpnd),
+    // Anything `interp_emit` doesn't model (CCall, Lea, the C-stack
+    // push/pop family, floating-point ops, ...). Carries the original Op
+    // purely so `Interpreter::run` can panic with a useful message.
+    Unsupported(Op),
+}
+
+/// The condition flags `Cmp`/`Test` set and `Jcc`/`CSel` read back, mirroring
+/// the subset of EFLAGS this backend's condition codes depend on.
+#[derive(Clone, Copy, Debug, Default)]
+struct Flags {
+    zero: bool,
+    sign: bool,
+    carry: bool,
+    overflow: bool,
+}
+
+impl Flags {
+    fn holds(self, cond: ICond) -> bool {
+        match cond {
+            ICond::Z | ICond::E => self.zero,
+            ICond::Nz | ICond::Ne => !self.zero,
+            ICond::L => self.sign != self.overflow,
+            ICond::Ge => self.sign == self.overflow,
+            ICond::Le => self.zero || (self.sign != self.overflow),
+            ICond::G => !self.zero && (self.sign == self.overflow),
+            ICond::Be => self.carry || self.zero,
+            ICond::O => self.overflow,
+        }
+    }
+}
+
+/// Why `Interpreter::run` stopped.
+#[derive(Clone, Copy, Debug)]
+pub enum InterpExit {
+    /// Hit a `CRet` with the given return value.
+    Returned(i64),
+    /// Took a branch to a `CodePtr`/`FunPtr` target -- the
+    /// interpreter's analogue of falling off the end of a JIT-compiled
+    /// block into a side exit or a C function.
+    Halted(Target),
+}
+
+/// A small register-machine interpreter for `IInsn` programs. Registers
+/// are addressed by the same `reg_no` the real register allocator handed
+/// out, so a program compiled once through `alloc_regs` can be hand off to
+/// either this or `x86_emit` and be expected to touch the same registers.
+///
+/// `Mem` operands dereference real host memory through the address held
+/// in their base register, exactly like the native code they stand in
+/// for -- this is what makes comparing final memory state against a
+/// native run meaningful, rather than just comparing two interpretations
+/// of the same toy model.
+pub struct Interpreter {
+    regs: [i64; 16],
+    flags: Flags,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self { regs: [0; 16], flags: Flags::default() }
+    }
+
+    pub fn reg(&self, reg_no: u8) -> i64 {
+        self.regs[reg_no as usize]
+    }
+
+    pub fn set_reg(&mut self, reg_no: u8, val: i64) {
+        self.regs[reg_no as usize] = val;
+    }
+
+    fn read(&self, opnd: IOpnd) -> i64 {
+        match opnd {
+            IOpnd::Imm(val) => val,
+            IOpnd::Reg(reg_no) => self.reg(reg_no),
+            IOpnd::Mem { base_reg, disp } => {
+                let addr = (self.reg(base_reg) + disp as i64) as *const i64;
+                // SAFETY: this dereferences the same address the native
+                // backend would compute for the equivalent `Mem` operand;
+                // callers are expected to point base registers at real,
+                // suitably-sized, 8-byte-aligned storage before running.
+                unsafe { addr.read_unaligned() }
+            },
+        }
+    }
+
+    fn write_mem(&self, base_reg: u8, disp: i32, val: i64) {
+        let addr = (self.reg(base_reg) + disp as i64) as *mut i64;
+        // SAFETY: see `read`.
+        unsafe { addr.write_unaligned(val) };
+    }
+
+    fn set_cmp_flags(&mut self, left: i64, right: i64) {
+        let (result, carry) = (left as u64).overflowing_sub(right as u64);
+        let result = result as i64;
+        self.flags = Flags {
+            zero: result == 0,
+            sign: result < 0,
+            carry,
+            overflow: ((left ^ right) & (left ^ result)) < 0,
+        };
+    }
+
+    fn set_test_flags(&mut self, left: i64, right: i64) {
+        let result = left & right;
+        self.flags = Flags { zero: result == 0, sign: result < 0, carry: false, overflow: false };
+    }
+
+    /// Run `prog` from the start until a `Ret` or a branch to a halting
+    /// target stops it.
+    pub fn run(&mut self, prog: &[IInsn]) -> InterpExit {
+        let mut pc = 0usize;
+
+        loop {
+            let insn = prog.get(pc).unwrap_or_else(|| {
+                panic!("interpreter ran off the end of the program (missing Ret/Halt at pc {pc})")
+            });
+
+            match insn {
+                IInsn::Jmp(target) => match target {
+                    ITarget::Offset(dest) => { pc = *dest; continue; },
+                    ITarget::Halt(t) => return InterpExit::Halted(*t),
+                },
+                IInsn::Jcc(cond, target) => {
+                    if self.flags.holds(*cond) {
+                        match target {
+                            ITarget::Offset(dest) => { pc = *dest; continue; },
+                            ITarget::Halt(t) => return InterpExit::Halted(*t),
+                        }
+                    }
+                },
+                IInsn::Ret(opnd) => return InterpExit::Returned(self.read(*opnd)),
+                IInsn::Unsupported(op) => panic!(
+                    "Interpreter does not model {op:?}; differential testing only covers the ops interp_emit translates"
+                ),
+
+                IInsn::Add { left, right, out } => self.set_reg(*out, self.read(*left).wrapping_add(self.read(*right))),
+                IInsn::Sub { left, right, out } => self.set_reg(*out, self.read(*left).wrapping_sub(self.read(*right))),
+                IInsn::And { left, right, out } => self.set_reg(*out, self.read(*left) & self.read(*right)),
+                IInsn::Or { left, right, out } => self.set_reg(*out, self.read(*left) | self.read(*right)),
+                IInsn::Xor { left, right, out } => self.set_reg(*out, self.read(*left) ^ self.read(*right)),
+                IInsn::Mul { left, right, out } |
+                IInsn::SMul { left, right, out } => self.set_reg(*out, self.read(*left).wrapping_mul(self.read(*right))),
+                IInsn::UMul { left, right, out } => self.set_reg(*out, (self.read(*left) as u64).wrapping_mul(self.read(*right) as u64) as i64),
+                IInsn::SDiv { left, right, out } => self.set_reg(*out, self.read(*left).wrapping_div(self.read(*right))),
+                IInsn::UDiv { left, right, out } => self.set_reg(*out, ((self.read(*left) as u64).wrapping_div(self.read(*right) as u64)) as i64),
+                IInsn::SMod { left, right, out } => self.set_reg(*out, self.read(*left).wrapping_rem(self.read(*right))),
+                IInsn::UMod { left, right, out } => self.set_reg(*out, ((self.read(*left) as u64).wrapping_rem(self.read(*right) as u64)) as i64),
+                IInsn::Not { opnd, out } => self.set_reg(*out, !self.read(*opnd)),
+                IInsn::LShift { opnd, shift, out } => self.set_reg(*out, self.read(*opnd).wrapping_shl(self.read(*shift) as u32)),
+                IInsn::RShift { opnd, shift, out } => self.set_reg(*out, self.read(*opnd).wrapping_shr(self.read(*shift) as u32)),
+                IInsn::URShift { opnd, shift, out } => self.set_reg(*out, ((self.read(*opnd) as u64).wrapping_shr(self.read(*shift) as u32)) as i64),
+
+                // The interpreter's register file has no per-value width,
+                // so a 32-to-64-bit sign extension is a no-op here: the
+                // full i64 is already in hand.
+                IInsn::LoadSExt { opnd, out } | IInsn::Load { opnd, out } => self.set_reg(*out, self.read(*opnd)),
+
+                IInsn::Mov { dest, src } => {
+                    let val = self.read(*src);
+                    match dest {
+                        IOpnd::Reg(reg_no) => self.set_reg(*reg_no, val),
+                        IOpnd::Mem { base_reg, disp } => self.write_mem(*base_reg, *disp, val),
+                        IOpnd::Imm(_) => unreachable!("mov destination can't be an immediate"),
+                    }
+                },
+                IInsn::Store { dest, src } => {
+                    let val = self.read(*src);
+                    match dest {
+                        IOpnd::Mem { base_reg, disp } => self.write_mem(*base_reg, *disp, val),
+                        _ => unreachable!("store destination must be memory"),
+                    }
+                },
+
+                IInsn::Cmp { left, right } => self.set_cmp_flags(self.read(*left), self.read(*right)),
+                IInsn::Test { left, right } => self.set_test_flags(self.read(*left), self.read(*right)),
+
+                IInsn::IncrCounter { mem, value } => {
+                    let IOpnd::Mem { base_reg, disp } = mem else { unreachable!("IncrCounter's mem operand must be memory") };
+                    let cur = self.read(*mem);
+                    self.write_mem(*base_reg, *disp, cur.wrapping_add(self.read(*value)));
+                },
+                IInsn::AtomicAdd { mem, val, out } => {
+                    let IOpnd::Mem { base_reg, disp } = mem else { unreachable!("AtomicAdd's mem operand must be memory") };
+                    let cur = self.read(*mem);
+                    self.write_mem(*base_reg, *disp, cur.wrapping_add(self.read(*val)));
+                    self.set_reg(*out, cur);
+                },
+                IInsn::AtomicSub { mem, val, out } => {
+                    let IOpnd::Mem { base_reg, disp } = mem else { unreachable!("AtomicSub's mem operand must be memory") };
+                    let cur = self.read(*mem);
+                    self.write_mem(*base_reg, *disp, cur.wrapping_sub(self.read(*val)));
+                    self.set_reg(*out, cur);
+                },
+                IInsn::AtomicXchg { mem, val, out } => {
+                    let IOpnd::Mem { base_reg, disp } = mem else { unreachable!("AtomicXchg's mem operand must be memory") };
+                    let cur = self.read(*mem);
+                    self.write_mem(*base_reg, *disp, self.read(*val));
+                    self.set_reg(*out, cur);
+                },
+                IInsn::CmpXchg { mem, expected, desired, out } => {
+                    let IOpnd::Mem { base_reg, disp } = mem else { unreachable!("CmpXchg's mem operand must be memory") };
+                    let cur = self.read(*mem);
+                    let exp = self.read(*expected);
+                    if cur == exp {
+                        self.write_mem(*base_reg, *disp, self.read(*desired));
+                    }
+                    self.flags.zero = cur == exp;
+                    self.set_reg(*out, cur);
+                },
+                IInsn::AtomicCmpXchg { mem, expected, desired, out } => {
+                    let IOpnd::Mem { base_reg, disp } = mem else { unreachable!("AtomicCmpXchg's mem operand must be memory") };
+                    let cur = self.read(*mem);
+                    let exp = self.read(*expected);
+                    let success = cur == exp;
+                    if success {
+                        self.write_mem(*base_reg, *disp, self.read(*desired));
+                    }
+                    self.flags.zero = success;
+                    self.set_reg(*out, success as i64);
+                },
+
+                IInsn::CSel { cond, truthy, falsy, out } => {
+                    let val = if self.flags.holds(*cond) { self.read(*truthy) } else { self.read(*falsy) };
+                    self.set_reg(*out, val);
+                },
+            }
+
+            pc += 1;
+        }
     }
 }
 
@@ -813,6 +1775,253 @@ mod tests {
         assert_eq!(format!("{:x}", cb), "817804000000f0");
     }
 
+    #[test]
+    fn test_emit_fadd() {
+        let (mut asm, mut cb) = setup_asm();
+
+        let _ = asm.fadd(Opnd::Reg(XMM0_REG), Opnd::Reg(XMM1_REG));
+        asm.compile_with_num_regs(&mut cb, 1);
+
+        assert_eq!(format!("{:x}", cb), "f20f10c0f20f58c1");
+    }
+
+    #[test]
+    fn test_emit_fsub() {
+        let (mut asm, mut cb) = setup_asm();
+
+        let _ = asm.fsub(Opnd::Reg(XMM0_REG), Opnd::Reg(XMM1_REG));
+        asm.compile_with_num_regs(&mut cb, 1);
+
+        assert_eq!(format!("{:x}", cb), "f20f10c0f20f5cc1");
+    }
+
+    #[test]
+    fn test_emit_fmul() {
+        let (mut asm, mut cb) = setup_asm();
+
+        let _ = asm.fmul(Opnd::Reg(XMM0_REG), Opnd::Reg(XMM1_REG));
+        asm.compile_with_num_regs(&mut cb, 1);
+
+        assert_eq!(format!("{:x}", cb), "f20f10c0f20f59c1");
+    }
+
+    #[test]
+    fn test_emit_fdiv() {
+        let (mut asm, mut cb) = setup_asm();
+
+        let _ = asm.fdiv(Opnd::Reg(XMM0_REG), Opnd::Reg(XMM1_REG));
+        asm.compile_with_num_regs(&mut cb, 1);
+
+        assert_eq!(format!("{:x}", cb), "f20f10c0f20f5ec1");
+    }
+
+    #[test]
+    fn test_emit_fload_mem() {
+        let (mut asm, mut cb) = setup_asm();
+
+        let shape_opnd = Opnd::mem(64, Opnd::Reg(RAX_REG), 0);
+
+        let _ = asm.fload(shape_opnd);
+        asm.compile_with_num_regs(&mut cb, 1);
+
+        assert_eq!(format!("{:x}", cb), "f20f1000");
+    }
+
+    #[test]
+    fn test_emit_fstore_mem() {
+        let (mut asm, mut cb) = setup_asm();
+
+        let shape_opnd = Opnd::mem(64, Opnd::Reg(RAX_REG), 0);
+
+        asm.fstore(shape_opnd, Opnd::Reg(XMM1_REG));
+        asm.compile_with_num_regs(&mut cb, 1);
+
+        assert_eq!(format!("{:x}", cb), "f20f1108");
+    }
+
+    #[test]
+    fn test_interp_add_and_ret() {
+        let mut interp = Interpreter::new();
+        interp.set_reg(0, 1);
+        interp.set_reg(1, 2);
+
+        let prog = [
+            IInsn::Add { left: IOpnd::Reg(0), right: IOpnd::Reg(1), out: 2 },
+            IInsn::Ret(IOpnd::Reg(2)),
+        ];
+
+        assert!(matches!(interp.run(&prog), InterpExit::Returned(3)));
+    }
+
+    #[test]
+    fn test_interp_csel_picks_truthy_branch_on_equal() {
+        let mut interp = Interpreter::new();
+        interp.set_reg(0, 5);
+
+        let prog = [
+            IInsn::Cmp { left: IOpnd::Reg(0), right: IOpnd::Imm(5) },
+            IInsn::CSel { cond: ICond::E, truthy: IOpnd::Imm(1), falsy: IOpnd::Imm(0), out: 1 },
+            IInsn::Ret(IOpnd::Reg(1)),
+        ];
+
+        assert!(matches!(interp.run(&prog), InterpExit::Returned(1)));
+    }
+
+    #[test]
+    fn test_interp_jle_loop_counts_down_to_zero() {
+        let mut interp = Interpreter::new();
+        interp.set_reg(0, 3);
+
+        // reg0 -= 1; cmp reg0, 0; jle done; jmp loop_start
+        let prog = [
+            IInsn::Sub { left: IOpnd::Reg(0), right: IOpnd::Imm(1), out: 0 },
+            IInsn::Cmp { left: IOpnd::Reg(0), right: IOpnd::Imm(0) },
+            IInsn::Jcc(ICond::Le, ITarget::Offset(4)),
+            IInsn::Jmp(ITarget::Offset(0)),
+            IInsn::Ret(IOpnd::Reg(0)),
+        ];
+
+        assert!(matches!(interp.run(&prog), InterpExit::Returned(0)));
+    }
+
+    #[test]
+    fn test_interp_halts_on_code_ptr_target() {
+        let mut interp = Interpreter::new();
+        let target = Target::FunPtr(std::ptr::null());
+
+        let prog = [IInsn::Jmp(ITarget::Halt(target))];
+
+        match interp.run(&prog) {
+            InterpExit::Halted(Target::FunPtr(ptr)) => assert!(ptr.is_null()),
+            other => panic!("expected a halt, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_interp_store_and_load_round_trip_through_memory() {
+        let mut interp = Interpreter::new();
+        let mut slot: i64 = 0;
+        interp.set_reg(0, &mut slot as *mut i64 as i64);
+
+        let prog = [
+            IInsn::Store { dest: IOpnd::Mem { base_reg: 0, disp: 0 }, src: IOpnd::Imm(42) },
+            IInsn::Load { opnd: IOpnd::Mem { base_reg: 0, disp: 0 }, out: 1 },
+            IInsn::Ret(IOpnd::Reg(1)),
+        ];
+
+        assert!(matches!(interp.run(&prog), InterpExit::Returned(42)));
+        assert_eq!(slot, 42);
+    }
+
+    #[test]
+    fn test_interp_atomic_sub_returns_pre_subtract_value() {
+        let mut interp = Interpreter::new();
+        let mut slot: i64 = 10;
+        interp.set_reg(0, &mut slot as *mut i64 as i64);
+        interp.set_reg(1, 3);
+
+        let prog = [
+            IInsn::AtomicSub { mem: IOpnd::Mem { base_reg: 0, disp: 0 }, val: IOpnd::Reg(1), out: 2 },
+            IInsn::Ret(IOpnd::Reg(2)),
+        ];
+
+        assert!(matches!(interp.run(&prog), InterpExit::Returned(10)));
+        assert_eq!(slot, 7);
+    }
+
+    #[test]
+    fn test_interp_atomic_xchg_swaps_and_returns_old_value() {
+        let mut interp = Interpreter::new();
+        let mut slot: i64 = 10;
+        interp.set_reg(0, &mut slot as *mut i64 as i64);
+        interp.set_reg(1, 99);
+
+        let prog = [
+            IInsn::AtomicXchg { mem: IOpnd::Mem { base_reg: 0, disp: 0 }, val: IOpnd::Reg(1), out: 2 },
+            IInsn::Ret(IOpnd::Reg(2)),
+        ];
+
+        assert!(matches!(interp.run(&prog), InterpExit::Returned(10)));
+        assert_eq!(slot, 99);
+    }
+
+    #[test]
+    fn test_interp_atomic_cmpxchg_reports_success_flag_not_old_value() {
+        let mut interp = Interpreter::new();
+        let mut slot: i64 = 10;
+        interp.set_reg(0, &mut slot as *mut i64 as i64);
+
+        // Expected value doesn't match, so the swap should fail and leave
+        // memory untouched.
+        let failing_prog = [
+            IInsn::AtomicCmpXchg { mem: IOpnd::Mem { base_reg: 0, disp: 0 }, expected: IOpnd::Imm(0), desired: IOpnd::Imm(55), out: 1 },
+            IInsn::Ret(IOpnd::Reg(1)),
+        ];
+        assert!(matches!(interp.run(&failing_prog), InterpExit::Returned(0)));
+        assert_eq!(slot, 10);
+
+        let succeeding_prog = [
+            IInsn::AtomicCmpXchg { mem: IOpnd::Mem { base_reg: 0, disp: 0 }, expected: IOpnd::Imm(10), desired: IOpnd::Imm(55), out: 1 },
+            IInsn::Ret(IOpnd::Reg(1)),
+        ];
+        assert!(matches!(interp.run(&succeeding_prog), InterpExit::Returned(1)));
+        assert_eq!(slot, 55);
+    }
+
+    #[test]
+    fn test_interp_mul_truncates_to_low_64_bits() {
+        let mut interp = Interpreter::new();
+        interp.set_reg(0, 6);
+        interp.set_reg(1, 7);
+
+        let prog = [
+            IInsn::Mul { left: IOpnd::Reg(0), right: IOpnd::Reg(1), out: 2 },
+            IInsn::Ret(IOpnd::Reg(2)),
+        ];
+
+        assert!(matches!(interp.run(&prog), InterpExit::Returned(42)));
+    }
+
+    #[test]
+    fn test_interp_sdiv_and_smod_match_truncating_division() {
+        let mut interp = Interpreter::new();
+        interp.set_reg(0, -7);
+        interp.set_reg(1, 2);
+
+        let quotient_prog = [
+            IInsn::SDiv { left: IOpnd::Reg(0), right: IOpnd::Reg(1), out: 2 },
+            IInsn::Ret(IOpnd::Reg(2)),
+        ];
+        assert!(matches!(interp.run(&quotient_prog), InterpExit::Returned(-3)));
+
+        let remainder_prog = [
+            IInsn::SMod { left: IOpnd::Reg(0), right: IOpnd::Reg(1), out: 2 },
+            IInsn::Ret(IOpnd::Reg(2)),
+        ];
+        assert!(matches!(interp.run(&remainder_prog), InterpExit::Returned(-1)));
+    }
+
+    #[test]
+    fn test_interp_udiv_and_umod_treat_operands_as_unsigned() {
+        let mut interp = Interpreter::new();
+        // -1i64 reinterpreted as u64 is the largest unsigned value.
+        interp.set_reg(0, -1);
+        interp.set_reg(1, 10);
+
+        let quotient_prog = [
+            IInsn::UDiv { left: IOpnd::Reg(0), right: IOpnd::Reg(1), out: 2 },
+            IInsn::Ret(IOpnd::Reg(2)),
+        ];
+        let InterpExit::Returned(quotient) = interp.run(&quotient_prog) else { panic!("expected a return") };
+        assert_eq!(quotient as u64, (u64::MAX) / 10);
+
+        let remainder_prog = [
+            IInsn::UMod { left: IOpnd::Reg(0), right: IOpnd::Reg(1), out: 2 },
+            IInsn::Ret(IOpnd::Reg(2)),
+        ];
+        assert!(matches!(interp.run(&remainder_prog), InterpExit::Returned(5)));
+    }
+
     #[test]
     fn test_emit_or_lt_32_bits() {
         let (mut asm, mut cb) = setup_asm();